@@ -0,0 +1,119 @@
+//! Local APIC + IOAPIC interrupt delivery — the modern alternative to the
+//! legacy PIC remap/EOI path in `interrupts.rs`. Selected by the build-time
+//! `interrupts::USE_APIC` switch plus a runtime CPUID check, so hardware or
+//! emulators without APIC support keep working through the PIC fallback.
+//!
+//! The LAPIC/IOAPIC MMIO windows (`0xFEE0_0000`/`0xFEC0_0000`) are accessed
+//! as raw physical addresses, the same assumption `vga_buffer` makes about
+//! `0xB8000` — there's no paging offset plumbed through here yet.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::interrupts::outb;
+
+const LAPIC_BASE: u64 = 0xFEE0_0000;
+const LAPIC_REG_SPURIOUS: u64 = 0xF0;
+const LAPIC_REG_EOI: u64 = 0xB0;
+const LAPIC_REG_LVT_TIMER: u64 = 0x320;
+const LAPIC_REG_TIMER_INITIAL_COUNT: u64 = 0x380;
+const LAPIC_REG_TIMER_DIVIDE: u64 = 0x3E0;
+
+const SPURIOUS_VECTOR: u8 = 0xFF;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const TIMER_DIVIDE_BY_1: u32 = 0b1011;
+
+/// IOAPIC MMIO base for the standard single-IOAPIC PC layout.
+const IOAPIC_BASE: u64 = 0xFEC0_0000;
+const IOAPIC_REG_WINDOW: u64 = 0x10;
+/// Redirection table entry 0 (IRQ0) low dword register index; entry N's
+/// low/high dwords sit at `0x10 + 2*N` / `0x10 + 2*N + 1`.
+const IOAPIC_REDTBL_BASE: u32 = 0x10;
+
+/// Set once `init` succeeds, so `interrupts::send_eoi` knows whether to
+/// write the LAPIC EOI register or fall back to the PIC EOI sequence.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// CPUID leaf 1, EDX bit 9 ("APIC on chip").
+pub fn is_supported() -> bool {
+    let edx: u32;
+    unsafe {
+        asm!(
+            "push rbx",
+            "cpuid",
+            "pop rbx",
+            inout("eax") 1u32 => _,
+            out("edx") edx,
+            out("ecx") _,
+            options(nostack, preserves_flags),
+        );
+    }
+    edx & (1 << 9) != 0
+}
+
+unsafe fn lapic_write(reg: u64, value: u32) {
+    unsafe {
+        ((LAPIC_BASE + reg) as *mut u32).write_volatile(value);
+    }
+}
+
+unsafe fn ioapic_write(reg: u32, value: u32) {
+    unsafe {
+        (IOAPIC_BASE as *mut u32).write_volatile(reg);
+        ((IOAPIC_BASE + IOAPIC_REG_WINDOW) as *mut u32).write_volatile(value);
+    }
+}
+
+/// Mask every legacy PIC line so a spurious PIC interrupt can't race the
+/// APIC path once it's live.
+unsafe fn mask_pic() {
+    unsafe {
+        outb(0x21, 0xFF);
+        outb(0xA1, 0xFF);
+    }
+}
+
+/// Enable the Local APIC, route the keyboard IRQ (IOAPIC pin 1) to
+/// `keyboard_vector`, and mask the legacy PIC. Call instead of `remap_pic`
+/// once `is_supported()` confirms the CPU has one.
+pub fn init(keyboard_vector: u8) {
+    unsafe {
+        mask_pic();
+
+        lapic_write(
+            LAPIC_REG_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32,
+        );
+
+        // Destination APIC ID 0 (this CPU) in the high dword, vector +
+        // fixed delivery mode (all other bits clear) in the low dword.
+        let irq1_low = IOAPIC_REDTBL_BASE + 1 * 2;
+        let irq1_high = irq1_low + 1;
+        ioapic_write(irq1_high, 0);
+        ioapic_write(irq1_low, keyboard_vector as u32);
+    }
+
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Configure the LAPIC timer in periodic mode and start it — the APIC
+/// path's replacement for `time::init_pit`.
+pub fn start_periodic_timer(initial_count: u32, vector: u8) {
+    unsafe {
+        lapic_write(LAPIC_REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_1);
+        lapic_write(LAPIC_REG_LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+        lapic_write(LAPIC_REG_TIMER_INITIAL_COUNT, initial_count);
+    }
+}
+
+/// Signal end-of-interrupt to the Local APIC.
+pub unsafe fn send_eoi() {
+    unsafe {
+        lapic_write(LAPIC_REG_EOI, 0);
+    }
+}
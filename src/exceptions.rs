@@ -1,26 +1,138 @@
-//! Exception handlers scaffolding (x86_64).
+//! Exception handlers for x86_64, with a recoverable dispatch layer.
 //!
-//! Handlers you likely want early:
-//! - Page Fault (#PF, vector 14): read CR2, decode error code, print info.
-//! - General Protection Fault (#GP, vector 13): print error code/state.
-//! - Double Fault (#DF, vector 8): requires IST for robust handling.
+//! - Page Fault (#PF, vector 14): read CR2, decode error code, then offer the
+//!   fault to any handler registered via [`register_page_fault_handler`]
+//!   before falling back to the fatal panic-print-halt path.
+//! - General Protection Fault (#GP, vector 13): same idea, via
+//!   [`register_vector_handler`].
+//! - Double Fault (#DF, vector 8): stays unconditionally fatal — it runs on
+//!   its own IST stack specifically because recovery can't be trusted.
+//!
+//! Registered handlers let a future subsystem (demand paging, a guard-page
+//! allocator, …) map a page and return [`FaultOutcome::Resume`] so the CPU
+//! retries the faulting instruction on `iretq`, instead of every fault being
+//! an immediate halt. We use interrupt gates (not trap gates) for these
+//! vectors, as today, so the handler itself can't be re-entered by a nested
+//! maskable interrupt while it's deciding the outcome.
 //!
 //! Topics to read:
 //! - IDT gate types (interrupt vs trap gate) and DPL
 //! - Error code bits for #PF/#GP; CR2 for faulting linear address
 //! - TSS + IST stack for #DF
-//! - iretq frame layout in long mode
-//!
-//! Wiring instructions:
-//! - In `interrupts::init()`, set IDT entries for vectors 8,13,14 to these handlers.
-//! - Consider using trap gate (0x8F00) for some exceptions.
 
 use core::{hint::spin_loop, sync::atomic::{AtomicBool, Ordering}};
+use core::cell::UnsafeCell;
 use x86_64::structures::idt::InterruptStackFrame;
 use x86_64::registers::control::Cr2;
+use x86_64::instructions::interrupts::without_interrupts;
 
 use crate::println;
 
+/// What a registered fault handler decided should happen next.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FaultOutcome {
+    /// Retry the faulting instruction as-is (the common case for a
+    /// just-mapped page fault).
+    Resume,
+    /// Nothing could recover this fault; run the panic-print-halt path.
+    Fatal,
+}
+
+/// Decoded `#PF` error-code bits plus the faulting linear address (CR2),
+/// handed to page-fault handlers so they don't have to re-decode either.
+#[derive(Clone, Copy)]
+pub struct PageFaultContext {
+    pub address: u64,
+    pub present: bool,
+    pub write: bool,
+    pub user_mode: bool,
+    pub reserved_violation: bool,
+    pub instruction_fetch: bool,
+}
+
+pub type PageFaultHandlerFn = fn(&PageFaultContext) -> FaultOutcome;
+pub type VectorHandlerFn = fn(vector: u8, error_code: u64) -> FaultOutcome;
+
+const MAX_PAGE_FAULT_HANDLERS: usize = 4;
+const MAX_VECTOR_HANDLERS: usize = 4;
+
+struct HandlerTable {
+    page_fault: [Option<PageFaultHandlerFn>; MAX_PAGE_FAULT_HANDLERS],
+    vector: [Option<(u8, VectorHandlerFn)>; MAX_VECTOR_HANDLERS],
+}
+
+struct SharedHandlerTable(UnsafeCell<HandlerTable>);
+
+unsafe impl Sync for SharedHandlerTable {}
+
+impl SharedHandlerTable {
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut HandlerTable) -> R,
+    {
+        without_interrupts(|| unsafe { f(&mut *self.0.get()) })
+    }
+}
+
+static HANDLERS: SharedHandlerTable = SharedHandlerTable(UnsafeCell::new(HandlerTable {
+    page_fault: [None; MAX_PAGE_FAULT_HANDLERS],
+    vector: [None; MAX_VECTOR_HANDLERS],
+}));
+
+/// Register a handler offered every `#PF` before the fatal fallback runs.
+/// Handlers are tried in registration order; the first to return something
+/// other than `Fatal` wins.
+pub fn register_page_fault_handler(handler: PageFaultHandlerFn) {
+    HANDLERS.with(|table| {
+        for slot in table.page_fault.iter_mut() {
+            if slot.is_none() {
+                *slot = Some(handler);
+                return;
+            }
+        }
+    });
+}
+
+/// Register a handler keyed by vector number. Only `#GP` (13) consults these
+/// handlers; one registered for any other vector is never called.
+pub fn register_vector_handler(vector: u8, handler: VectorHandlerFn) {
+    HANDLERS.with(|table| {
+        for slot in table.vector.iter_mut() {
+            if slot.is_none() {
+                *slot = Some((vector, handler));
+                return;
+            }
+        }
+    });
+}
+
+fn dispatch_page_fault(ctx: &PageFaultContext) -> FaultOutcome {
+    HANDLERS.with(|table| {
+        for slot in table.page_fault.iter().flatten() {
+            match slot(ctx) {
+                FaultOutcome::Fatal => continue,
+                outcome => return outcome,
+            }
+        }
+        FaultOutcome::Fatal
+    })
+}
+
+fn dispatch_vector(vector: u8, error_code: u64) -> FaultOutcome {
+    HANDLERS.with(|table| {
+        for (registered_vector, handler) in table.vector.iter().flatten() {
+            if *registered_vector != vector {
+                continue;
+            }
+            match handler(vector, error_code) {
+                FaultOutcome::Fatal => continue,
+                outcome => return outcome,
+            }
+        }
+        FaultOutcome::Fatal
+    })
+}
+
 // Arm this flag to trigger a nested #PF inside the #PF handler, which
 // will escalate to a #DF handled on a dedicated IST stack.
 static TRIGGER_DF_ON_PF: AtomicBool = AtomicBool::new(false);
@@ -52,6 +164,11 @@ pub extern "x86-interrupt" fn gpf_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    match dispatch_vector(13, error_code) {
+        FaultOutcome::Resume => return,
+        FaultOutcome::Fatal => {}
+    }
+
     // Mirror minimal info to serial as well.
     println!("EXCEPTION: GENERAL PROTECTION FAULT, ec={:#x}\n{:#?}", error_code, stack_frame);
     crate::serial::write_str("EXCEPTION: GENERAL PROTECTION FAULT\n");
@@ -65,15 +182,24 @@ pub extern "x86-interrupt" fn page_fault_handler(
 ) {
     // Faulting linear address is in CR2
     let addr = Cr2::read_raw();
-    let p = (error_code & 1) != 0;          // 0=not-present, 1=protection
-    let wr = (error_code & (1 << 1)) != 0;  // 0=read, 1=write
-    let us = (error_code & (1 << 2)) != 0;  // 0=supervisor, 1=user
-    let rsv = (error_code & (1 << 3)) != 0; // reserved-bit violation
-    let id = (error_code & (1 << 4)) != 0;  // instruction fetch
+    let ctx = PageFaultContext {
+        address: addr,
+        present: (error_code & 1) != 0,             // 0=not-present, 1=protection
+        write: (error_code & (1 << 1)) != 0,         // 0=read, 1=write
+        user_mode: (error_code & (1 << 2)) != 0,     // 0=supervisor, 1=user
+        reserved_violation: (error_code & (1 << 3)) != 0,
+        instruction_fetch: (error_code & (1 << 4)) != 0,
+    };
+
+    match dispatch_page_fault(&ctx) {
+        FaultOutcome::Resume => return,
+        FaultOutcome::Fatal => {}
+    }
 
     println!(
         "EXCEPTION: PAGE FAULT @ {:#x}, ec={:#x} P={} WR={} US={} RSVD={} ID={}",
-        addr, error_code, p, wr, us, rsv, id
+        ctx.address, error_code, ctx.present, ctx.write, ctx.user_mode,
+        ctx.reserved_violation, ctx.instruction_fetch
     );
     crate::serial::write_str("EXCEPTION: PAGE FAULT\n");
     println!("{:#?}", stack_frame);
@@ -84,6 +210,6 @@ pub extern "x86-interrupt" fn page_fault_handler(
             core::ptr::read_volatile(ptr);
         }
     }
-    // Fatal by default: do not resume faulting instruction
+    // No handler claimed the fault: fatal, do not resume.
     loop { spin_loop() }
 }
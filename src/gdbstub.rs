@@ -0,0 +1,502 @@
+//! GDB Remote Serial Protocol stub over COM1.
+//!
+//! Lets a host `gdb` attach with `target remote /dev/ttyS0` (or a pty) and
+//! debug the kernel at source level. Packets are framed as `$<payload>#<hh>`
+//! where `<hh>` is the two-hex-digit modulo-256 checksum of the payload;
+//! we ack each inbound packet with `+` (good checksum) or `-` (bad, please
+//! resend). `#BP` and `#DB` are routed here instead of spinning forever so a
+//! breakpoint or single-step actually stops at a live register/memory prompt.
+//!
+//! Allocation-free: packets live in fixed buffers and breakpoints in a fixed
+//! table, sized for a handful of concurrent debug sessions/breakpoints.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+use crate::serial;
+
+const MAX_PACKET: usize = 512;
+const MAX_BREAKPOINTS: usize = 16;
+const INT3: u8 = 0xCC;
+
+/// GPR snapshot in the exact order GDB expects for x86_64 `g`/`G` packets.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct GdbRegisters {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub eflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+const NUM_REGS: usize = 24;
+const TRAP_FLAG: u64 = 1 << 8;
+
+impl GdbRegisters {
+    fn as_words(&self) -> [u64; NUM_REGS] {
+        [
+            self.rax, self.rbx, self.rcx, self.rdx, self.rsi, self.rdi, self.rbp, self.rsp,
+            self.r8, self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15,
+            self.rip, self.eflags, self.cs, self.ss, self.ds, self.es, self.fs, self.gs,
+        ]
+    }
+
+    fn set_word(&mut self, index: usize, value: u64) {
+        match index {
+            0 => self.rax = value,
+            1 => self.rbx = value,
+            2 => self.rcx = value,
+            3 => self.rdx = value,
+            4 => self.rsi = value,
+            5 => self.rdi = value,
+            6 => self.rbp = value,
+            7 => self.rsp = value,
+            8 => self.r8 = value,
+            9 => self.r9 = value,
+            10 => self.r10 = value,
+            11 => self.r11 = value,
+            12 => self.r12 = value,
+            13 => self.r13 = value,
+            14 => self.r14 = value,
+            15 => self.r15 = value,
+            16 => self.rip = value,
+            17 => self.eflags = value,
+            18 => self.cs = value,
+            19 => self.ss = value,
+            20 => self.ds = value,
+            21 => self.es = value,
+            22 => self.fs = value,
+            23 => self.gs = value,
+            _ => {}
+        }
+    }
+}
+
+struct Breakpoint {
+    addr: u64,
+    original_byte: u8,
+    active: bool,
+}
+
+static mut BREAKPOINTS: [Breakpoint; MAX_BREAKPOINTS] = [const {
+    Breakpoint { addr: 0, original_byte: 0, active: false }
+}; MAX_BREAKPOINTS];
+
+/// What the command loop decided to do once it stops reading packets.
+enum Action {
+    Continue,
+    SingleStep,
+}
+
+/// Entry point called from the `#BP`/`#DB` trampolines with a live GPR
+/// snapshot. Blocks in a command loop until the host sends `c` or `s`.
+pub fn handle_trap(regs: &mut GdbRegisters, signal: u8) {
+    // A software breakpoint's INT3 leaves rip one past the patched byte;
+    // step back so `g`/continue resume at the original instruction.
+    if signal == 5 {
+        if let Some(bp) = unsafe { find_breakpoint(regs.rip.wrapping_sub(1)) } {
+            regs.rip = bp;
+        }
+    }
+
+    send_stop_reply(signal);
+
+    let mut packet = [0u8; MAX_PACKET];
+    loop {
+        let len = match recv_packet(&mut packet) {
+            Some(len) => len,
+            None => continue,
+        };
+
+        match dispatch(&packet[..len], regs) {
+            Some(Action::Continue) => {
+                regs.eflags &= !TRAP_FLAG;
+                return;
+            }
+            Some(Action::SingleStep) => {
+                regs.eflags |= TRAP_FLAG;
+                return;
+            }
+            None => {}
+        }
+    }
+}
+
+fn dispatch(payload: &[u8], regs: &mut GdbRegisters) -> Option<Action> {
+    if payload.is_empty() {
+        send_packet(b"");
+        return None;
+    }
+
+    match payload[0] {
+        b'?' => send_stop_reply(5),
+        b'g' => send_registers(regs),
+        b'G' => {
+            write_registers(&payload[1..], regs);
+            send_packet(b"OK");
+        }
+        b'm' => read_memory(&payload[1..]),
+        b'M' => {
+            write_memory(&payload[1..]);
+            send_packet(b"OK");
+        }
+        b'c' => return Some(Action::Continue),
+        b's' => return Some(Action::SingleStep),
+        b'Z' if payload.get(1) == Some(&b'0') => {
+            set_breakpoint(&payload[2..]);
+        }
+        b'z' if payload.get(1) == Some(&b'0') => {
+            clear_breakpoint(&payload[2..]);
+        }
+        _ => send_packet(b""),
+    }
+    None
+}
+
+fn send_stop_reply(signal: u8) {
+    let mut reply = [0u8; 3];
+    reply[0] = b'S';
+    write_hex_byte(signal, &mut reply[1..3]);
+    send_packet(&reply);
+}
+
+fn send_registers(regs: &GdbRegisters) {
+    let mut out = [0u8; NUM_REGS * 16];
+    for (i, word) in regs.as_words().iter().enumerate() {
+        write_hex_le_u64(*word, &mut out[i * 16..i * 16 + 16]);
+    }
+    send_packet(&out);
+}
+
+fn write_registers(hex: &[u8], regs: &mut GdbRegisters) {
+    for i in 0..NUM_REGS {
+        let start = i * 16;
+        if start + 16 > hex.len() {
+            break;
+        }
+        if let Some(word) = parse_hex_le_u64(&hex[start..start + 16]) {
+            regs.set_word(i, word);
+        }
+    }
+}
+
+fn read_memory(args: &[u8]) {
+    let (addr, len) = match parse_addr_len(args) {
+        Some(v) => v,
+        None => return send_packet(b"E01"),
+    };
+    let mut out = [0u8; 512];
+    if len * 2 > out.len() {
+        return send_packet(b"E02");
+    }
+    for i in 0..len {
+        let byte = unsafe { core::ptr::read_volatile((addr + i as u64) as *const u8) };
+        write_hex_byte(byte, &mut out[i * 2..i * 2 + 2]);
+    }
+    send_packet(&out[..len * 2]);
+}
+
+fn write_memory(args: &[u8]) {
+    let split = match args.iter().position(|&b| b == b':') {
+        Some(i) => i,
+        None => return,
+    };
+    let (addr, len) = match parse_addr_len(&args[..split]) {
+        Some(v) => v,
+        None => return,
+    };
+    let data = &args[split + 1..];
+    for i in 0..len {
+        if let Some(byte) = parse_hex_byte(&data[i * 2..i * 2 + 2]) {
+            unsafe { core::ptr::write_volatile((addr + i as u64) as *mut u8, byte) };
+        }
+    }
+}
+
+fn set_breakpoint(args: &[u8]) {
+    let split = match args.iter().position(|&b| b == b',') {
+        Some(i) => i,
+        None => return,
+    };
+    let addr = match parse_hex_u64(&args[..split]) {
+        Some(a) => a,
+        None => return,
+    };
+    unsafe {
+        for bp in BREAKPOINTS.iter_mut() {
+            if !bp.active {
+                let original = core::ptr::read_volatile(addr as *const u8);
+                core::ptr::write_volatile(addr as *mut u8, INT3);
+                bp.addr = addr;
+                bp.original_byte = original;
+                bp.active = true;
+                break;
+            }
+        }
+    }
+    send_packet(b"OK");
+}
+
+fn clear_breakpoint(args: &[u8]) {
+    let split = match args.iter().position(|&b| b == b',') {
+        Some(i) => i,
+        None => return,
+    };
+    let addr = match parse_hex_u64(&args[..split]) {
+        Some(a) => a,
+        None => return,
+    };
+    unsafe {
+        for bp in BREAKPOINTS.iter_mut() {
+            if bp.active && bp.addr == addr {
+                core::ptr::write_volatile(addr as *mut u8, bp.original_byte);
+                bp.active = false;
+                break;
+            }
+        }
+    }
+    send_packet(b"OK");
+}
+
+unsafe fn find_breakpoint(addr: u64) -> Option<u64> {
+    for bp in unsafe { BREAKPOINTS.iter() } {
+        if bp.active && bp.addr == addr {
+            return Some(bp.addr);
+        }
+    }
+    None
+}
+
+fn parse_addr_len(args: &[u8]) -> Option<(u64, usize)> {
+    let split = args.iter().position(|&b| b == b',')?;
+    let addr = parse_hex_u64(&args[..split])?;
+    let len = parse_hex_u64(&args[split + 1..])? as usize;
+    Some((addr, len))
+}
+
+// --- packet framing -------------------------------------------------------
+
+fn recv_packet(buf: &mut [u8]) -> Option<usize> {
+    // Wait for the start-of-packet marker, dropping anything else
+    // (including a stray Ctrl-C, which we treat as "already stopped").
+    loop {
+        // `read_byte` waits on the IRQ-fed queue, which never fills while
+        // we're inside a #BP/#DB interrupt-gate handler (IF=0 for the rest
+        // of the stay) — poll the UART directly instead.
+        let byte = serial::read_byte_blocking();
+        if byte == b'$' {
+            break;
+        }
+        if byte == 0x03 {
+            return None;
+        }
+    }
+
+    let mut len = 0;
+    let mut checksum: u8 = 0;
+    loop {
+        let byte = serial::read_byte_blocking();
+        if byte == b'#' {
+            break;
+        }
+        if len < buf.len() {
+            buf[len] = byte;
+            len += 1;
+        }
+        checksum = checksum.wrapping_add(byte);
+    }
+
+    let hi = serial::read_byte_blocking();
+    let lo = serial::read_byte_blocking();
+    let expected = parse_hex_byte(&[hi, lo]).unwrap_or(0xFF);
+
+    if expected == checksum {
+        serial::write_byte_blocking(b'+');
+        Some(len)
+    } else {
+        serial::write_byte_blocking(b'-');
+        None
+    }
+}
+
+fn send_packet(payload: &[u8]) {
+    let checksum = payload.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    serial::write_byte_blocking(b'$');
+    for &byte in payload {
+        serial::write_byte_blocking(byte);
+    }
+    serial::write_byte_blocking(b'#');
+    let mut hex = [0u8; 2];
+    write_hex_byte(checksum, &mut hex);
+    serial::write_byte_blocking(hex[0]);
+    serial::write_byte_blocking(hex[1]);
+}
+
+// --- hex helpers -----------------------------------------------------------
+
+fn hex_digit(value: u8) -> u8 {
+    match value {
+        0..=9 => b'0' + value,
+        _ => b'a' + (value - 10),
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn write_hex_byte(value: u8, out: &mut [u8]) {
+    out[0] = hex_digit(value >> 4);
+    out[1] = hex_digit(value & 0xF);
+}
+
+fn parse_hex_byte(hex: &[u8]) -> Option<u8> {
+    let hi = hex_value(*hex.first()?)?;
+    let lo = hex_value(*hex.get(1)?)?;
+    Some((hi << 4) | lo)
+}
+
+fn parse_hex_u64(hex: &[u8]) -> Option<u64> {
+    if hex.is_empty() {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for &byte in hex {
+        value = (value << 4) | hex_value(byte)? as u64;
+    }
+    Some(value)
+}
+
+/// GDB's `g`/`G` registers are little-endian byte order; write/parse them
+/// byte-by-byte rather than as one big-endian hex number.
+fn write_hex_le_u64(value: u64, out: &mut [u8]) {
+    for i in 0..8 {
+        let byte = (value >> (i * 8)) as u8;
+        write_hex_byte(byte, &mut out[i * 2..i * 2 + 2]);
+    }
+}
+
+fn parse_hex_le_u64(hex: &[u8]) -> Option<u64> {
+    let mut value: u64 = 0;
+    for i in 0..8 {
+        let byte = parse_hex_byte(&hex[i * 2..i * 2 + 2])?;
+        value |= (byte as u64) << (i * 8);
+    }
+    Some(value)
+}
+
+// --- trap entry trampolines -------------------------------------------------
+
+/// Naked entry point for vector 3 (#BP). Saves the full GPR file onto the
+/// stack in `SavedGprs` order, hands a pointer to [`breakpoint_entry`],
+/// then restores registers and `iretq`s back.
+#[unsafe(naked)]
+pub extern "C" fn breakpoint_trap_entry() {
+    core::arch::naked_asm!(
+        "push rbp",
+        "push r15", "push r14", "push r13", "push r12",
+        "push r11", "push r10", "push r9", "push r8",
+        "push rdi", "push rsi", "push rdx", "push rcx", "push rbx", "push rax",
+        "mov rdi, rsp",
+        "mov rsi, 5", // SIGTRAP
+        "call {entry}",
+        "pop rax", "pop rbx", "pop rcx", "pop rdx", "pop rsi", "pop rdi",
+        "pop r8", "pop r9", "pop r10", "pop r11",
+        "pop r12", "pop r13", "pop r14", "pop r15",
+        "pop rbp",
+        "iretq",
+        entry = sym breakpoint_entry,
+    )
+}
+
+/// Naked entry point for vector 1 (#DB), used for single-stepping.
+#[unsafe(naked)]
+pub extern "C" fn debug_trap_entry() {
+    core::arch::naked_asm!(
+        "push rbp",
+        "push r15", "push r14", "push r13", "push r12",
+        "push r11", "push r10", "push r9", "push r8",
+        "push rdi", "push rsi", "push rdx", "push rcx", "push rbx", "push rax",
+        "mov rdi, rsp",
+        "mov rsi, 5", // SIGTRAP
+        "call {entry}",
+        "pop rax", "pop rbx", "pop rcx", "pop rdx", "pop rsi", "pop rdi",
+        "pop r8", "pop r9", "pop r10", "pop r11",
+        "pop r12", "pop r13", "pop r14", "pop r15",
+        "pop rbp",
+        "iretq",
+        entry = sym debug_entry,
+    )
+}
+
+/// Layout pushed by the trampolines above, followed by the CPU-pushed
+/// `InterruptStackFrame` (rip, cs, rflags, rsp, ss).
+#[repr(C)]
+struct SavedGprs {
+    rax: u64, rbx: u64, rcx: u64, rdx: u64, rsi: u64, rdi: u64,
+    r8: u64, r9: u64, r10: u64, r11: u64, r12: u64, r13: u64, r14: u64, r15: u64,
+    rbp: u64,
+    rip: u64, cs: u64, rflags: u64, rsp: u64, ss: u64,
+}
+
+extern "C" fn breakpoint_entry(frame: *mut SavedGprs, signal: u8) {
+    unsafe { dispatch_from_frame(frame, signal) }
+}
+
+extern "C" fn debug_entry(frame: *mut SavedGprs, signal: u8) {
+    unsafe { dispatch_from_frame(frame, signal) }
+}
+
+unsafe fn dispatch_from_frame(frame: *mut SavedGprs, signal: u8) {
+    let saved = unsafe { &mut *frame };
+    let mut regs = GdbRegisters {
+        rax: saved.rax, rbx: saved.rbx, rcx: saved.rcx, rdx: saved.rdx,
+        rsi: saved.rsi, rdi: saved.rdi, rbp: saved.rbp, rsp: saved.rsp,
+        r8: saved.r8, r9: saved.r9, r10: saved.r10, r11: saved.r11,
+        r12: saved.r12, r13: saved.r13, r14: saved.r14, r15: saved.r15,
+        rip: saved.rip, eflags: saved.rflags,
+        cs: saved.cs, ss: saved.ss, ds: 0, es: 0, fs: 0, gs: 0,
+    };
+
+    handle_trap(&mut regs, signal);
+
+    saved.rax = regs.rax; saved.rbx = regs.rbx; saved.rcx = regs.rcx; saved.rdx = regs.rdx;
+    saved.rsi = regs.rsi; saved.rdi = regs.rdi; saved.rbp = regs.rbp;
+    saved.r8 = regs.r8; saved.r9 = regs.r9; saved.r10 = regs.r10; saved.r11 = regs.r11;
+    saved.r12 = regs.r12; saved.r13 = regs.r13; saved.r14 = regs.r14; saved.r15 = regs.r15;
+    saved.rip = regs.rip;
+    saved.rflags = regs.eflags;
+}
+
+/// Call once after `serial::init_unsafe_16550_default()` and before
+/// `interrupts::init()` so the IDT wiring there can reference these entries.
+pub fn init() {
+    let _ = breakpoint_trap_entry as extern "C" fn();
+    let _ = debug_trap_entry as extern "C" fn();
+}
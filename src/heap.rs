@@ -0,0 +1,270 @@
+//! Kernel heap: a static backing region plus a first-fit free-list
+//! `#[global_allocator]`, so `alloc::string::String`/`Vec` become available
+//! to callers that don't need `FixedString`'s hard capacity limit.
+//!
+//! Must be initialized with [`init`] before any `alloc` collection is
+//! touched — early-boot code (panic messages, anything before `init` runs)
+//! should keep using `FixedString`.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use x86_64::instructions::interrupts;
+
+/// Size of the static region backing the heap.
+const HEAP_SIZE: usize = 256 * 1024;
+
+#[repr(align(16))]
+struct HeapStorage([u8; HEAP_SIZE]);
+
+static mut HEAP_STORAGE: HeapStorage = HeapStorage([0; HEAP_SIZE]);
+
+/// Minimum block size: every free/allocated block must be able to hold a
+/// [`FreeBlock`] node once freed, so carve-off remainders smaller than this
+/// are left attached to the block being allocated instead of split off.
+const MIN_BLOCK_SIZE: usize = mem::size_of::<FreeBlock>();
+
+/// Intrusive free-list node, written directly into the free memory it
+/// describes. The list is kept sorted by address so `dealloc` can coalesce
+/// with its immediate neighbours in one pass.
+struct FreeBlock {
+    size: usize,
+    next: Option<NonNull<FreeBlock>>,
+}
+
+impl FreeBlock {
+    fn end_addr(&self) -> usize {
+        self as *const _ as usize + self.size
+    }
+}
+
+struct FreeListAllocator {
+    head: Option<NonNull<FreeBlock>>,
+}
+
+impl FreeListAllocator {
+    const fn empty() -> Self {
+        Self { head: None }
+    }
+
+    /// Register the whole heap region as one free block. Safety: `heap_start`
+    /// must point to `heap_size` bytes of memory nobody else is using, and
+    /// `init` must only be called once.
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        unsafe {
+            self.add_free_region(heap_start, heap_size);
+        }
+    }
+
+    /// Insert `[addr, addr + size)` into the free list in address order,
+    /// coalescing with the previous/next block when they're adjacent.
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        if size < MIN_BLOCK_SIZE {
+            return;
+        }
+        debug_assert_eq!(addr % mem::align_of::<FreeBlock>(), 0);
+
+        // Find the insertion point: the first node whose address is past
+        // `addr`, keeping a pointer to the slot that links to it.
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut cursor = self.head;
+        while let Some(node) = cursor {
+            if unsafe { node.as_ref().end_addr() } > addr {
+                break;
+            }
+            prev = cursor;
+            cursor = unsafe { node.as_ref().next };
+        }
+
+        let mut new_addr = addr;
+        let mut new_size = size;
+
+        // Merge with the previous block if it ends exactly at `addr`.
+        if let Some(mut prev_node) = prev {
+            let prev_ref = unsafe { prev_node.as_mut() };
+            if prev_ref.end_addr() == new_addr {
+                new_addr = prev_node.as_ptr() as usize;
+                new_size += prev_ref.size;
+                cursor = prev_ref.next;
+                prev = self.prev_of(prev_node);
+            }
+        }
+
+        // Merge with the next block if `new` ends exactly where it begins.
+        if let Some(next_node) = cursor {
+            if new_addr + new_size == next_node.as_ptr() as usize {
+                new_size += unsafe { next_node.as_ref().size };
+                cursor = unsafe { next_node.as_ref().next };
+            }
+        }
+
+        let node_ptr = new_addr as *mut FreeBlock;
+        unsafe {
+            node_ptr.write(FreeBlock {
+                size: new_size,
+                next: cursor,
+            });
+        }
+        let node = unsafe { NonNull::new_unchecked(node_ptr) };
+
+        match prev {
+            Some(mut prev_node) => unsafe { prev_node.as_mut().next = Some(node) },
+            None => self.head = Some(node),
+        }
+    }
+
+    /// Linear scan to find the node that links to `target`, used only while
+    /// re-threading the list after merging with a predecessor above.
+    fn prev_of(&self, target: NonNull<FreeBlock>) -> Option<NonNull<FreeBlock>> {
+        let mut cursor = self.head;
+        let mut prev = None;
+        while let Some(node) = cursor {
+            if node == target {
+                return prev;
+            }
+            prev = Some(node);
+            cursor = unsafe { node.as_ref().next };
+        }
+        None
+    }
+
+    /// First-fit search: walk the free list and take the first block that
+    /// can hold `size` bytes aligned to `align`, removing it from the list
+    /// and returning its `(start, size, alloc_start)`.
+    fn find_region(&mut self, size: usize, align: usize) -> Option<(usize, usize, usize)> {
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut cursor = self.head;
+
+        while let Some(node) = cursor {
+            let block = unsafe { node.as_ref() };
+            if let Some(alloc_start) = Self::fits(block, size, align) {
+                let block_start = node.as_ptr() as usize;
+                let block_size = block.size;
+                let next = block.next;
+                match prev {
+                    Some(mut prev_node) => unsafe { prev_node.as_mut().next = next },
+                    None => self.head = next,
+                }
+                return Some((block_start, block_size, alloc_start));
+            }
+            prev = cursor;
+            cursor = block.next;
+        }
+        None
+    }
+
+    /// Returns the aligned allocation start within `block` if `size` bytes
+    /// fit, leaving either nothing or at least `MIN_BLOCK_SIZE` bytes spare
+    /// on the trailing side (the leading gap, if any, is always reclaimable
+    /// since it's pushed out to at least `MIN_BLOCK_SIZE` and carved off whole).
+    fn fits(block: &FreeBlock, size: usize, align: usize) -> Option<usize> {
+        let block_start = block as *const _ as usize;
+        let mut alloc_start = align_up(block_start, align);
+        if alloc_start != block_start && alloc_start - block_start < MIN_BLOCK_SIZE {
+            alloc_start = align_up(block_start + MIN_BLOCK_SIZE, align);
+        }
+        let alloc_end = alloc_start.checked_add(size)?;
+        if alloc_end > block.end_addr() {
+            return None;
+        }
+
+        let excess = block.end_addr() - alloc_end;
+        if excess != 0 && excess < MIN_BLOCK_SIZE {
+            return None;
+        }
+        Some(alloc_start)
+    }
+
+    /// First-fit allocation. Splits the chosen block, returning any leading
+    /// (alignment padding) and trailing (size) slack to the free list.
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = adjusted_layout(layout);
+        let Some((block_start, block_size, alloc_start)) = self.find_region(size, align) else {
+            return core::ptr::null_mut();
+        };
+        let block_end = block_start + block_size;
+        let alloc_end = alloc_start + size;
+
+        if alloc_start > block_start {
+            unsafe {
+                self.add_free_region(block_start, alloc_start - block_start);
+            }
+        }
+        if alloc_end < block_end {
+            unsafe {
+                self.add_free_region(alloc_end, block_end - alloc_end);
+            }
+        }
+
+        alloc_start as *mut u8
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _align) = adjusted_layout(layout);
+        unsafe {
+            self.add_free_region(ptr as usize, size);
+        }
+    }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// Every block must be large enough to later hold a `FreeBlock` node when
+/// freed, and aligned at least as strictly as one. `size` is also rounded up
+/// to a multiple of `align` — `alloc`'s trailing-slack call to
+/// `add_free_region(alloc_end, ...)` computes `alloc_end` as `alloc_start +
+/// size`, and `alloc_start` is already `align`-aligned, so an unrounded
+/// `size` could leave `alloc_end` (and therefore the `FreeBlock` written
+/// there) misaligned.
+fn adjusted_layout(layout: Layout) -> (usize, usize) {
+    let align = layout.align().max(mem::align_of::<FreeBlock>());
+    let size = align_up(layout.size().max(MIN_BLOCK_SIZE), align);
+    (size, align)
+}
+
+struct LockedHeap(UnsafeCell<FreeListAllocator>);
+
+impl LockedHeap {
+    const fn new() -> Self {
+        Self(UnsafeCell::new(FreeListAllocator::empty()))
+    }
+
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut FreeListAllocator) -> R,
+    {
+        interrupts::without_interrupts(|| unsafe { f(&mut *self.0.get()) })
+    }
+}
+
+unsafe impl Sync for LockedHeap {}
+
+unsafe impl GlobalAlloc for LockedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with(|heap| heap.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with(|heap| unsafe { heap.dealloc(ptr, layout) });
+    }
+}
+
+#[cfg_attr(not(test), global_allocator)]
+static ALLOCATOR: LockedHeap = LockedHeap::new();
+
+/// Bring the heap online. Must run once, before any `alloc` collection is
+/// created.
+pub fn init() {
+    let heap_start = ptr::addr_of!(HEAP_STORAGE) as usize;
+    ALLOCATOR.with(|heap| unsafe { heap.init(heap_start, HEAP_SIZE) });
+}
+
+#[cfg(not(test))]
+#[alloc_error_handler]
+fn on_alloc_error(layout: Layout) -> ! {
+    panic!("heap allocation failed: {} bytes (align {})", layout.size(), layout.align());
+}
@@ -1,21 +1,27 @@
-use crate::simple_string::FixedString;
+//! Shell input history.
+//!
+//! Backed by `alloc::vec::Vec<alloc::string::String>` now that `heap::init`
+//! runs before the shell is constructed, so entries are no longer capped at
+//! a fixed length the way `FixedString` would force; the entry count is
+//! still capped at `MAX_ENTRIES`. Requires the
+//! heap to be initialized first — early-boot code still uses `FixedString`.
 
-const MAX_ENTRIES: usize = 32;
-const ENTRY_CAPACITY: usize = 128;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Oldest entries are dropped once history grows past this many lines, so
+/// a very long session doesn't grow the heap without bound.
+const MAX_ENTRIES: usize = 256;
 
 pub struct InputHistory {
-    entries: [FixedString<ENTRY_CAPACITY>; MAX_ENTRIES],
-    len: usize,
-    head: usize,
+    entries: Vec<String>,
     cursor: usize,
 }
 
 impl InputHistory {
     pub fn new() -> Self {
         Self {
-            entries: core::array::from_fn(|_| FixedString::new()),
-            len: 0,
-            head: 0,
+            entries: Vec::new(),
             cursor: 0,
         }
     }
@@ -26,88 +32,63 @@ impl InputHistory {
             return;
         }
 
-        if self.len > 0 {
-            if let Some(last) = self.latest() {
-                if last == line {
-                    self.reset_navigation();
-                    return;
-                }
+        if let Some(last) = self.latest() {
+            if last == line {
+                self.reset_navigation();
+                return;
             }
         }
 
-        let target = if self.len < MAX_ENTRIES {
-            let idx = (self.head + self.len) % MAX_ENTRIES;
-            self.len += 1;
-            idx
-        } else {
-            let idx = self.head;
-            self.head = (self.head + 1) % MAX_ENTRIES;
-            idx
-        };
-
-        self.entries[target].clear();
-        let _ = self.entries[target].push_str(line);
-        self.cursor = self.len;
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(String::from(line));
+        self.cursor = self.entries.len();
     }
 
     pub fn previous(&mut self) -> Option<&str> {
-        if self.len == 0 {
+        if self.entries.is_empty() {
             return None;
         }
 
-        if self.cursor == 0 {
-            // already at oldest
-        } else if self.cursor > self.len {
-            self.cursor = self.len.saturating_sub(1);
-        } else {
+        if self.cursor > self.entries.len() {
+            self.cursor = self.entries.len();
+        }
+        if self.cursor > 0 {
             self.cursor -= 1;
         }
 
-        self.entry_index(self.cursor)
-            .map(|idx| self.entries[idx].as_str())
+        self.entries.get(self.cursor).map(String::as_str)
     }
 
     pub fn next(&mut self) -> Option<&str> {
-        if self.cursor >= self.len {
-            self.cursor = self.len;
+        if self.cursor >= self.entries.len() {
+            self.cursor = self.entries.len();
             return None;
         }
 
         self.cursor += 1;
-        if self.cursor >= self.len {
-            self.cursor = self.len;
+        if self.cursor >= self.entries.len() {
+            self.cursor = self.entries.len();
             return None;
         }
 
-        self.entry_index(self.cursor)
-            .map(|idx| self.entries[idx].as_str())
+        self.entries.get(self.cursor).map(String::as_str)
     }
 
     pub fn latest(&self) -> Option<&str> {
-        if self.len == 0 {
-            None
-        } else {
-            let idx = self.entry_index(self.len - 1)?;
-            Some(self.entries[idx].as_str())
-        }
+        self.entries.last().map(String::as_str)
     }
 
     pub fn reset_navigation(&mut self) {
-        self.cursor = self.len;
+        self.cursor = self.entries.len();
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.entries.is_empty()
     }
 
     pub fn is_at_current(&self) -> bool {
-        self.cursor >= self.len
-    }
-
-    fn entry_index(&self, logical: usize) -> Option<usize> {
-        if logical >= self.len || self.len == 0 {
-            return None;
-        }
-        Some((self.head + logical) % MAX_ENTRIES)
+        self.cursor >= self.entries.len()
     }
 }
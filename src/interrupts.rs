@@ -4,14 +4,21 @@
 //! remaps the legacy PIC, and exposes low-level helpers to access
 //! the PIC/data ports.
 
-use core::{arch::asm, ptr};
+use core::arch::asm;
 use x86_64;
 use x86_64::instructions::segmentation::Segment;
 use x86_64::registers::segmentation::CS;
 use x86_64::structures::idt::InterruptStackFrame;
 
-use crate::{exceptions, keyboard, println};
+use crate::sync::Once;
+use crate::{apic, exceptions, keyboard};
 
+/// Build-time choice of interrupt controller. `false` keeps the legacy
+/// 8259 PIC path, which works on every target including emulators without
+/// APIC support; flip to `true` to prefer the Local APIC + IOAPIC path on
+/// hardware where `apic::is_supported()` confirms it's available. Either
+/// way `init` falls back to the PIC if the APIC path can't be used.
+pub const USE_APIC: bool = false;
 
 /// Represents the layout of a single IDT entry (interrupt gate).
 #[derive(Copy, Clone)]
@@ -68,30 +75,46 @@ pub struct Idtr {
 }
 
 const IDT_LEN: usize = 256;
-static mut IDT: [IdtEntry; IDT_LEN] = [IdtEntry::missing(); IDT_LEN];
+
+/// Built once, on first call to `init`, then only read — see
+/// `crate::sync::Once`.
+static IDT: Once<[IdtEntry; IDT_LEN]> = Once::new();
 
 /// Initialize the interrupt subsystem.
 pub fn init() {
-    unsafe {
-        IDT[InterruptIndex::Breakpoint as usize] =
-            IdtEntry::new(breakpoint_handler as *const () as usize);
-        IDT[InterruptIndex::Timer as usize] =
+    let idt = IDT.get_or_init(|| {
+        let mut idt = [IdtEntry::missing(); IDT_LEN];
+
+        idt[InterruptIndex::Breakpoint as usize] =
+            IdtEntry::new(crate::gdbstub::breakpoint_trap_entry as *const () as usize);
+        idt[InterruptIndex::Debug as usize] =
+            IdtEntry::new(crate::gdbstub::debug_trap_entry as *const () as usize);
+        idt[InterruptIndex::Timer as usize] =
             IdtEntry::new(timer_interrupt_handler as *const () as usize);
-        IDT[InterruptIndex::Keyboard as usize] =
+        idt[InterruptIndex::Keyboard as usize] =
             IdtEntry::new(keyboard_interrupt_handler as *const () as usize);
+        idt[InterruptIndex::Com1 as usize] =
+            IdtEntry::new(com1_interrupt_handler as *const () as usize);
 
         // Register key exception handlers
-        IDT[14] = IdtEntry::new(exceptions::page_fault_handler as *const () as usize);
-        IDT[13] = IdtEntry::new(exceptions::gpf_handler as *const () as usize);
+        idt[14] = IdtEntry::new(exceptions::page_fault_handler as *const () as usize);
+        idt[13] = IdtEntry::new(exceptions::gpf_handler as *const () as usize);
         // После реализации GDT+TSS включаем #DF с IST=1
-        IDT[8] = IdtEntry::new_with_ist(
+        idt[8] = IdtEntry::new_with_ist(
             exceptions::double_fault_handler as *const () as usize,
             crate::gdt::DOUBLE_FAULT_IST_INDEX_FOR_IDT as u8,
         );
-        // IDT[13] = IdtEntry::new(exceptions::gpf_handler as usize);
 
-        remap_pic();
-        load_idt(ptr::addr_of!(IDT).cast(), IDT_LEN);
+        idt
+    });
+
+    unsafe {
+        if USE_APIC && apic::is_supported() {
+            apic::init(InterruptIndex::Keyboard as u8);
+        } else {
+            remap_pic();
+        }
+        load_idt(idt.as_ptr(), IDT_LEN);
     }
 
     x86_64::instructions::interrupts::enable();
@@ -112,8 +135,14 @@ unsafe fn load_idt(idt: *const IdtEntry, len: usize) {
     }
 }
 
-/// Send End-Of-Interrupt to the Programmable Interrupt Controller.
+/// Send End-Of-Interrupt to whichever controller is currently routing
+/// interrupts (Local APIC if `apic::init` succeeded, else the legacy PIC).
 pub unsafe fn send_eoi(irq: u8) {
+    if apic::is_active() {
+        unsafe { apic::send_eoi() };
+        return;
+    }
+
     if irq >= 0x28 {
         unsafe { outb(0xA0, 0x20) }; // Slave PIC
     }
@@ -182,9 +211,11 @@ pub unsafe fn inb(port: u16) -> u8 {
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
+    Debug = 0x01,
     Breakpoint = 0x03,
     Timer = 0x20,
     Keyboard = 0x21,
+    Com1 = 0x24,
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(
@@ -197,13 +228,18 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     }
 }
 
+extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::serial::handle_irq();
+    unsafe {
+        send_eoi(InterruptIndex::Com1 as u8);
+    }
+}
+
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
     unsafe {
         send_eoi(InterruptIndex::Timer as u8);
     }
     crate::time::tick();
+    crate::scheduler::preempt();
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-}
@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use x86_64::instructions::interrupts;
 
+use crate::sync::SpinLock;
+
 const SCANCODE_QUEUE_CAPACITY: usize = 256;
 
 struct ScancodeQueue {
@@ -41,31 +43,242 @@ impl ScancodeQueue {
     }
 }
 
-struct SharedQueue(UnsafeCell<ScancodeQueue>);
+static QUEUE: SpinLock<ScancodeQueue> = SpinLock::new(ScancodeQueue::new());
+
+/// Called from the keyboard interrupt handler to enqueue the latest scancode.
+pub fn push_scancode(scancode: u8) {
+    QUEUE.with(|queue| queue.push(scancode));
+}
+
+/// Pops the next pending scancode if available.
+pub fn pop_scancode() -> Option<u8> {
+    QUEUE.with(|queue| queue.pop())
+}
+
+/// Bare Scancode Set 1 codes for the modifier keys the decoder tracks.
+mod scancode {
+    pub const LEFT_SHIFT: u8 = 0x2A;
+    pub const RIGHT_SHIFT: u8 = 0x36;
+    pub const LEFT_CTRL: u8 = 0x1D;
+    pub const LEFT_ALT: u8 = 0x38;
+    pub const CAPS_LOCK: u8 = 0x3A;
+}
+
+/// Keys with no sensible `char` representation, reported via
+/// `DecodedKey::RawKey` (mirrors the split the `pc-keyboard` crate uses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    ArrowUp,
+    ArrowDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedKey {
+    Unicode(char),
+    RawKey(KeyCode),
+}
+
+#[derive(Clone, Copy)]
+struct Modifiers {
+    left_shift: bool,
+    right_shift: bool,
+    ctrl: bool,
+    alt: bool,
+    caps_lock: bool,
+}
+
+impl Modifiers {
+    const fn new() -> Self {
+        Self {
+            left_shift: false,
+            right_shift: false,
+            ctrl: false,
+            alt: false,
+            caps_lock: false,
+        }
+    }
+
+    fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+}
+
+/// Tracks modifier state and the `0xE0` extended prefix across calls so
+/// scancodes can be decoded one byte at a time as they arrive.
+struct Decoder {
+    modifiers: Modifiers,
+    extended: bool,
+}
+
+impl Decoder {
+    const fn new() -> Self {
+        Self {
+            modifiers: Modifiers::new(),
+            extended: false,
+        }
+    }
+
+    fn decode(&mut self, scancode: u8) -> Option<DecodedKey> {
+        if scancode == 0xE0 {
+            self.extended = true;
+            return None;
+        }
+
+        let extended = core::mem::replace(&mut self.extended, false);
+        let released = scancode & 0x80 != 0;
+        let code = scancode & 0x7F;
+
+        if extended {
+            return Self::decode_extended(code, released);
+        }
+
+        match code {
+            scancode::LEFT_SHIFT => {
+                self.modifiers.left_shift = !released;
+                None
+            }
+            scancode::RIGHT_SHIFT => {
+                self.modifiers.right_shift = !released;
+                None
+            }
+            scancode::LEFT_CTRL => {
+                self.modifiers.ctrl = !released;
+                None
+            }
+            scancode::LEFT_ALT => {
+                self.modifiers.alt = !released;
+                None
+            }
+            scancode::CAPS_LOCK => {
+                // Caps Lock toggles on press, not hold.
+                if !released {
+                    self.modifiers.caps_lock = !self.modifiers.caps_lock;
+                }
+                None
+            }
+            _ if released => None,
+            _ => self.decode_unicode(code),
+        }
+    }
+
+    fn decode_extended(code: u8, released: bool) -> Option<DecodedKey> {
+        if released {
+            return None;
+        }
+        match code {
+            0x48 => Some(DecodedKey::RawKey(KeyCode::ArrowUp)),
+            0x50 => Some(DecodedKey::RawKey(KeyCode::ArrowDown)),
+            _ => None,
+        }
+    }
+
+    fn decode_unicode(&self, code: u8) -> Option<DecodedKey> {
+        if let Some(letter) = base_letter(code) {
+            let uppercase = self.modifiers.shift() ^ self.modifiers.caps_lock;
+            let ch = if uppercase { letter.to_ascii_uppercase() } else { letter };
+            return Some(DecodedKey::Unicode(ch));
+        }
+
+        let (plain, shifted_symbol) = base_symbol(code)?;
+        let ch = if self.modifiers.shift() { shifted_symbol } else { plain };
+        Some(DecodedKey::Unicode(ch))
+    }
+}
+
+/// Letter row scancodes (unshifted, lowercase); case is resolved separately
+/// from `shift`/`caps_lock`.
+fn base_letter(code: u8) -> Option<char> {
+    match code {
+        0x10 => Some('q'),
+        0x11 => Some('w'),
+        0x12 => Some('e'),
+        0x13 => Some('r'),
+        0x14 => Some('t'),
+        0x15 => Some('y'),
+        0x16 => Some('u'),
+        0x17 => Some('i'),
+        0x18 => Some('o'),
+        0x19 => Some('p'),
+        0x1E => Some('a'),
+        0x1F => Some('s'),
+        0x20 => Some('d'),
+        0x21 => Some('f'),
+        0x22 => Some('g'),
+        0x23 => Some('h'),
+        0x24 => Some('j'),
+        0x25 => Some('k'),
+        0x26 => Some('l'),
+        0x2C => Some('z'),
+        0x2D => Some('x'),
+        0x2E => Some('c'),
+        0x2F => Some('v'),
+        0x30 => Some('b'),
+        0x31 => Some('n'),
+        0x32 => Some('m'),
+        _ => None,
+    }
+}
 
-impl SharedQueue {
+/// Number/punctuation row scancodes as `(unshifted, shifted)` pairs.
+fn base_symbol(code: u8) -> Option<(char, char)> {
+    match code {
+        0x02 => Some(('1', '!')),
+        0x03 => Some(('2', '@')),
+        0x04 => Some(('3', '#')),
+        0x05 => Some(('4', '$')),
+        0x06 => Some(('5', '%')),
+        0x07 => Some(('6', '^')),
+        0x08 => Some(('7', '&')),
+        0x09 => Some(('8', '*')),
+        0x0A => Some(('9', '(')),
+        0x0B => Some(('0', ')')),
+        0x0C => Some(('-', '_')),
+        0x0D => Some(('=', '+')),
+        0x1A => Some(('[', '{')),
+        0x1B => Some((']', '}')),
+        0x27 => Some((';', ':')),
+        0x28 => Some(('\'', '"')),
+        0x29 => Some(('`', '~')),
+        0x2B => Some(('\\', '|')),
+        0x33 => Some((',', '<')),
+        0x34 => Some(('.', '>')),
+        0x35 => Some(('/', '?')),
+        0x39 => Some((' ', ' ')),
+        0x1C => Some(('\n', '\n')),
+        0x0F => Some(('\t', '\t')),
+        0x0E => Some(('\x08', '\x08')),
+        _ => None,
+    }
+}
+
+struct SharedDecoder(UnsafeCell<Decoder>);
+
+impl SharedDecoder {
     const fn new() -> Self {
-        Self(UnsafeCell::new(ScancodeQueue::new()))
+        Self(UnsafeCell::new(Decoder::new()))
     }
 
     fn with<F, R>(&self, f: F) -> R
     where
-        F: FnOnce(&mut ScancodeQueue) -> R,
+        F: FnOnce(&mut Decoder) -> R,
     {
         interrupts::without_interrupts(|| unsafe { f(&mut *self.0.get()) })
     }
 }
 
-unsafe impl Sync for SharedQueue {}
+unsafe impl Sync for SharedDecoder {}
 
-static QUEUE: SharedQueue = SharedQueue::new();
+static DECODER: SharedDecoder = SharedDecoder::new();
 
-/// Called from the keyboard interrupt handler to enqueue the latest scancode.
-pub fn push_scancode(scancode: u8) {
-    QUEUE.with(|queue| queue.push(scancode));
-}
-
-/// Pops the next pending scancode if available.
-pub fn pop_scancode() -> Option<u8> {
-    QUEUE.with(|queue| queue.pop())
+/// Drain pending scancodes, decoding through the stateful modifier-aware
+/// decoder, until one resolves to a key or the queue runs dry. Most
+/// scancodes (modifier presses/releases, key releases) decode to nothing
+/// and are consumed silently.
+pub fn next_key() -> Option<DecodedKey> {
+    loop {
+        let scancode = pop_scancode()?;
+        if let Some(key) = DECODER.with(|decoder| decoder.decode(scancode)) {
+            return Some(key);
+        }
+    }
 }
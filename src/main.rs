@@ -1,12 +1,28 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+#![feature(alloc_error_handler)]
 
+extern crate alloc;
+
+mod apic;
 mod vga_buffer;
 mod keyboard;
 mod shell;
 mod parser;
 mod history;
 mod simple_string;
+mod gdt;
+mod exceptions;
+mod interrupts;
+mod serial;
+mod gdbstub;
+mod time;
+mod vm;
+mod panic_print;
+mod heap;
+mod scheduler;
+mod sync;
+mod paging;
 use core::panic::PanicInfo;
 use bootloader::{entry_point, BootInfo};
 
@@ -17,12 +33,41 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 // env!("CARGO_PKG_AUTHORS")
 // env!("CARGO_PKG_REPOSITORY")
 
+#[cfg(not(test))]
 entry_point!(kernel_main);
 
-fn kernel_main(_boot_info: &'static BootInfo) -> ! {
-    shell::bootstrap(VERSION);
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    serial::init_unsafe_16550_default();
+    gdt::init();
+    gdbstub::init();
+    interrupts::init();
+    heap::init();
+    paging::init(boot_info);
+    scheduler::init();
+    if interrupts::USE_APIC && apic::is_active() {
+        // Placeholder initial count; real hardware needs this calibrated
+        // against the LAPIC timer's actual frequency (e.g. via the PIT)
+        // to land on the same ~100 Hz tick rate the PIC path uses.
+        apic::start_periodic_timer(0x0010_0000, interrupts::InterruptIndex::Timer as u8);
+    } else {
+        time::init_pit(100);
+    }
+
+    let mut keyboard_source = shell::KeyboardSource::new();
+    let mut serial_source = shell::SerialSource::new();
+    shell::bootstrap(
+        VERSION,
+        &mut [
+            &mut keyboard_source as &mut dyn shell::CharSource,
+            &mut serial_source as &mut dyn shell::CharSource,
+        ],
+    );
 }
+#[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    panic_print::print(info);
+    loop {
+        x86_64::instructions::hlt();
+    }
 }
@@ -7,6 +7,10 @@
 
 use core::arch::asm;
 
+use bootloader::bootinfo::{BootInfo, MemoryRegionType};
+
+use crate::sync::SpinLock;
+
 /// Number of entries per x86_64 page table.
 pub const ENTRIES_PER_TABLE: usize = 512;
 
@@ -39,6 +43,10 @@ impl PageTableEntry {
         (self.0 & flags::PRESENT) == 0
     }
 
+    pub fn is_huge(&self) -> bool {
+        (self.0 & flags::HUGE_PAGE) != 0
+    }
+
     pub fn addr(&self) -> u64 {
         self.0 & ADDRESS_MASK
     }
@@ -76,6 +84,9 @@ pub const FRAME_SIZE: u64 = 4096;
 
 pub trait FrameAllocator {
     fn allocate_frame(&mut self) -> Option<u64>;
+
+    /// Return a frame previously handed out by `allocate_frame` to the pool.
+    fn deallocate_frame(&mut self, frame: u64);
 }
 
 pub struct BumpFrameAllocator {
@@ -101,12 +112,193 @@ impl FrameAllocator for BumpFrameAllocator {
         self.next = align_up(self.next + FRAME_SIZE);
         Some(frame)
     }
+
+    /// The bump allocator never reclaims frames — see `BitmapFrameAllocator`
+    /// for that. Kept a no-op rather than removed so existing call sites that
+    /// only ever allocated (never freed) through this allocator still compile.
+    fn deallocate_frame(&mut self, _frame: u64) {}
 }
 
 const fn align_up(addr: u64) -> u64 {
     (addr + FRAME_SIZE - 1) & !(FRAME_SIZE - 1)
 }
 
+/// A reclaiming frame allocator backed by a bitmap: bit `N` tracks the frame
+/// at `region_start + N * FRAME_SIZE`. Unlike `BumpFrameAllocator`, frames
+/// can be freed and handed back out.
+///
+/// The bitmap's own storage is carved out of the front of the managed
+/// region itself (rounded up to whole frames and marked used), so callers
+/// only need to supply the region bounds and a `phys_offset` for translating
+/// physical addresses to the higher-half mapping that's already active —
+/// the same trick `Mapper` uses.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    region_start: u64,
+    frame_count: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// # Safety
+    /// `region_start..region_end` must be unused physical memory, and
+    /// `phys_offset` must map it to valid, writable virtual memory (as
+    /// `Mapper::table_mut` assumes elsewhere in this module).
+    pub unsafe fn init(region_start: u64, region_end: u64, phys_offset: u64, reserved_until: u64) -> Self {
+        let start = align_up(region_start);
+        let frame_count = ((region_end.saturating_sub(start)) / FRAME_SIZE) as usize;
+        let word_count = (frame_count + 63) / 64;
+        let bitmap_bytes = (word_count * 8) as u64;
+        let bitmap_frames = (align_up(bitmap_bytes) / FRAME_SIZE) as usize;
+
+        let bitmap = unsafe {
+            let ptr = (start + phys_offset) as *mut u64;
+            core::slice::from_raw_parts_mut(ptr, word_count)
+        };
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+
+        let mut allocator = Self { bitmap, region_start: start, frame_count };
+
+        // The frames holding the bitmap itself are always reserved...
+        for index in 0..bitmap_frames.min(frame_count) {
+            allocator.set_used(index);
+        }
+        // ...as is everything else below the caller's watermark (kernel
+        // image, page tables already built before this allocator existed).
+        let reserved_frames = if reserved_until > start {
+            ((reserved_until - start) / FRAME_SIZE) as usize
+        } else {
+            0
+        };
+        for index in 0..reserved_frames.min(frame_count) {
+            allocator.set_used(index);
+        }
+
+        allocator
+    }
+
+    fn set_used(&mut self, index: usize) {
+        self.bitmap[index / 64] |= 1 << (index % 64);
+    }
+
+    fn clear_used(&mut self, index: usize) {
+        self.bitmap[index / 64] &= !(1 << (index % 64));
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn index_of(&self, frame: u64) -> usize {
+        assert!(frame >= self.region_start, "frame {:#x} below managed region", frame);
+        assert_eq!(frame & (FRAME_SIZE - 1), 0, "frame {:#x} is not frame-aligned", frame);
+        let index = ((frame - self.region_start) / FRAME_SIZE) as usize;
+        assert!(index < self.frame_count, "frame {:#x} above managed region", frame);
+        index
+    }
+}
+
+impl FrameAllocator for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<u64> {
+        for word_index in 0..self.bitmap.len() {
+            if self.bitmap[word_index] == u64::MAX {
+                continue;
+            }
+            for bit in 0..64 {
+                let index = word_index * 64 + bit;
+                if index >= self.frame_count {
+                    break;
+                }
+                if self.bitmap[word_index] & (1 << bit) == 0 {
+                    self.bitmap[word_index] |= 1 << bit;
+                    return Some(self.region_start + index as u64 * FRAME_SIZE);
+                }
+            }
+        }
+        None
+    }
+
+    fn deallocate_frame(&mut self, frame: u64) {
+        let index = self.index_of(frame);
+        assert!(self.is_used(index), "double free of frame {:#x}", frame);
+        self.clear_used(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Build an allocator over `frame_count` frames without going through
+    /// `BitmapFrameAllocator::init`'s physical-memory requirement: the
+    /// bitmap just needs *some* writable, leaked storage, not real frames.
+    fn test_allocator(frame_count: usize) -> BitmapFrameAllocator {
+        let word_count = (frame_count + 63) / 64;
+        let bitmap: &'static mut [u64] = alloc::boxed::Box::leak(vec![0u64; word_count].into_boxed_slice());
+        BitmapFrameAllocator {
+            bitmap,
+            region_start: 0x1000,
+            frame_count,
+        }
+    }
+
+    #[test]
+    fn allocates_distinct_frames_in_order() {
+        let mut allocator = test_allocator(4);
+        assert_eq!(allocator.allocate_frame(), Some(0x1000));
+        assert_eq!(allocator.allocate_frame(), Some(0x1000 + FRAME_SIZE));
+        assert_eq!(allocator.allocate_frame(), Some(0x1000 + 2 * FRAME_SIZE));
+    }
+
+    #[test]
+    fn exhausts_after_frame_count_allocations() {
+        let mut allocator = test_allocator(2);
+        assert!(allocator.allocate_frame().is_some());
+        assert!(allocator.allocate_frame().is_some());
+        assert_eq!(allocator.allocate_frame(), None);
+    }
+
+    #[test]
+    fn freed_frame_is_reused() {
+        let mut allocator = test_allocator(2);
+        let first = allocator.allocate_frame().unwrap();
+        let second = allocator.allocate_frame().unwrap();
+        assert_eq!(allocator.allocate_frame(), None);
+
+        allocator.deallocate_frame(first);
+        assert_eq!(allocator.allocate_frame(), Some(first));
+
+        allocator.deallocate_frame(second);
+        assert_eq!(allocator.allocate_frame(), Some(second));
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free_panics() {
+        let mut allocator = test_allocator(2);
+        let frame = allocator.allocate_frame().unwrap();
+        allocator.deallocate_frame(frame);
+        allocator.deallocate_frame(frame);
+    }
+}
+
+/// Returned by `map_page`/`map_huge_page_2mib` when `remap` is false and
+/// `virt` is already mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyMapped {
+    pub virt: u64,
+    pub existing_phys: u64,
+}
+
+#[inline]
+unsafe fn invlpg(virt: u64) {
+    unsafe {
+        asm!("invlpg [{0}]", in(reg) virt, options(nostack, preserves_flags));
+    }
+}
+
 pub struct Mapper {
     pml4_phys: u64,
     phys_offset: u64,
@@ -139,19 +331,26 @@ impl Mapper {
         let end = align_up(end);
         while addr < end {
             unsafe {
-                self.map_page(addr, addr, flags, allocator);
+                // Identity ranges may legitimately be walked more than once
+                // (e.g. overlapping init calls); remapping to the same
+                // frame is harmless, so allow it here.
+                let _ = self.map_page(addr, addr, flags, true, allocator);
             }
             addr += FRAME_SIZE;
         }
     }
 
+    /// Map a single 4 KiB page. If `virt` is already mapped, this overwrites
+    /// the entry when `remap` is true, or returns `Err(AlreadyMapped)` with
+    /// the existing physical address when `remap` is false.
     pub unsafe fn map_page(
         &mut self,
         virt: u64,
         phys: u64,
         flags: u64,
+        remap: bool,
         allocator: &mut impl FrameAllocator,
-    ) {
+    ) -> Result<(), AlreadyMapped> {
         let mut table = self.pml4_phys;
         for &index in &[
             pml4_index(virt),
@@ -162,9 +361,146 @@ impl Mapper {
         }
         let last = unsafe { self.table_mut(table) };
         let entry = last.entry_mut(pt_index(virt));
-        if entry.is_unused() {
-            entry.set(phys, flags);
-        } // else: already mapped, keep existing mapping
+        if !entry.is_unused() && !remap {
+            return Err(AlreadyMapped { virt, existing_phys: entry.addr() });
+        }
+        entry.set(phys, flags);
+        unsafe { invlpg(virt) };
+        Ok(())
+    }
+
+    /// Map a single 2 MiB page directly at the PD level, skipping the PT
+    /// entirely — for large identity regions that would otherwise burn
+    /// thousands of page-table frames.
+    pub unsafe fn map_huge_page_2mib(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        flags: u64,
+        remap: bool,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), AlreadyMapped> {
+        debug_assert_eq!(virt & 0x1F_FFFF, 0, "2 MiB page must be 2 MiB aligned");
+        debug_assert_eq!(phys & 0x1F_FFFF, 0, "2 MiB frame must be 2 MiB aligned");
+
+        let mut table = self.pml4_phys;
+        for &index in &[pml4_index(virt), pdpt_index(virt)] {
+            table = unsafe { self.ensure_next_table(table, index, allocator) };
+        }
+        let pd = unsafe { self.table_mut(table) };
+        let entry = pd.entry_mut(pd_index(virt));
+        if !entry.is_unused() && !remap {
+            return Err(AlreadyMapped { virt, existing_phys: entry.addr() });
+        }
+        entry.set(phys, flags | flags::HUGE_PAGE);
+        unsafe { invlpg(virt) };
+        Ok(())
+    }
+
+    /// Remove the mapping at `virt`, returning the physical frame that was
+    /// mapped there (the caller owns giving it back to a `FrameAllocator` if
+    /// appropriate). Honors the `HUGE_PAGE` bit at the PDPT (1 GiB) and PD
+    /// (2 MiB) levels the same way `translate` does, clearing the huge entry
+    /// itself instead of walking into its frame as if it were a page table.
+    /// Flushes just this page from the TLB via `invlpg` rather than reloading
+    /// CR3.
+    pub unsafe fn unmap(&mut self, virt: u64) -> Option<u64> {
+        let pdpt_phys = unsafe {
+            let pml4 = self.table_mut(self.pml4_phys);
+            let entry = pml4.entry_mut(pml4_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            entry.addr()
+        };
+
+        let pd_phys = unsafe {
+            let pdpt = self.table_mut(pdpt_phys);
+            let entry = pdpt.entry_mut(pdpt_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            if entry.is_huge() {
+                let frame = entry.addr();
+                entry.clear();
+                invlpg(virt);
+                return Some(frame);
+            }
+            entry.addr()
+        };
+
+        let pt_phys = unsafe {
+            let pd = self.table_mut(pd_phys);
+            let entry = pd.entry_mut(pd_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            if entry.is_huge() {
+                let frame = entry.addr();
+                entry.clear();
+                invlpg(virt);
+                return Some(frame);
+            }
+            entry.addr()
+        };
+
+        unsafe {
+            let pt = self.table_mut(pt_phys);
+            let entry = pt.entry_mut(pt_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            let frame = entry.addr();
+            entry.clear();
+            invlpg(virt);
+            Some(frame)
+        }
+    }
+
+    /// Walk the tables read-only and resolve `virt` to its physical address,
+    /// honoring the `HUGE_PAGE` bit at the PDPT (1 GiB) and PD (2 MiB) levels.
+    pub unsafe fn translate(&self, virt: u64) -> Option<u64> {
+        let pdpt_phys = unsafe {
+            let pml4 = self.table_mut(self.pml4_phys);
+            let entry = pml4.entry_mut(pml4_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            entry.addr()
+        };
+
+        let pd_phys = unsafe {
+            let pdpt = self.table_mut(pdpt_phys);
+            let entry = pdpt.entry_mut(pdpt_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            if entry.is_huge() {
+                return Some(entry.addr() + (virt & 0x3FFF_FFFF));
+            }
+            entry.addr()
+        };
+
+        let pt_phys = unsafe {
+            let pd = self.table_mut(pd_phys);
+            let entry = pd.entry_mut(pd_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            if entry.is_huge() {
+                return Some(entry.addr() + (virt & 0x1F_FFFF));
+            }
+            entry.addr()
+        };
+
+        unsafe {
+            let pt = self.table_mut(pt_phys);
+            let entry = pt.entry_mut(pt_index(virt));
+            if entry.is_unused() {
+                return None;
+            }
+            Some(entry.addr() + (virt & 0xFFF))
+        }
     }
 
     pub unsafe fn activate(&self) {
@@ -219,6 +555,182 @@ pub unsafe fn read_cr3_phys() -> u64 {
     value & 0x000F_FFFF_FFFF_F000
 }
 
+struct PagingState {
+    allocator: BitmapFrameAllocator,
+    mapper: Mapper,
+}
+
+static PAGING: SpinLock<Option<PagingState>> = SpinLock::new(None);
+
+/// Bring the bitmap frame allocator and a `Mapper` over the bootloader's
+/// already-active page tables online, backed by the largest `Usable` region
+/// the bootloader's memory map reports. Must run once, after `heap::init`
+/// (so the frames it hands out are never the static heap region — that one
+/// lives in the kernel image, which the bootloader never marks `Usable`
+/// anyway) and before anything calls `allocate_frame`/`deallocate_frame`.
+pub fn init(boot_info: &'static BootInfo) {
+    let phys_offset = boot_info.physical_memory_offset;
+    let region = boot_info
+        .memory_map
+        .iter()
+        .filter(|region| region.region_type == MemoryRegionType::Usable)
+        .max_by_key(|region| region.range.end_frame_number - region.range.start_frame_number)
+        .expect("bootloader reported no Usable memory region");
+
+    let region_start = region.range.start_addr().as_u64();
+    let region_end = region.range.end_addr().as_u64();
+
+    let allocator = unsafe {
+        // Nothing below `region_start` needs reserving beyond the bitmap's
+        // own storage: the bootloader already excludes the kernel image and
+        // its own page tables from `Usable` regions.
+        BitmapFrameAllocator::init(region_start, region_end, phys_offset, region_start)
+    };
+    let mapper = unsafe { Mapper::from_existing(read_cr3_phys(), phys_offset) };
+
+    PAGING.with(|state| {
+        let state = state.insert(PagingState { allocator, mapper });
+        unsafe { self_check(state) };
+    });
+}
+
+/// Scratch virtual addresses used only by the self-check below — chosen far
+/// from both the kernel image and the `phys_offset` direct-map window (no
+/// user-space mappings exist yet, so low canonical addresses like these are
+/// guaranteed free), so mapping over them here can't clobber anything real.
+const SELF_CHECK_SCRATCH_VIRT: u64 = 0x0000_7000_0000_0000;
+const SELF_CHECK_SCRATCH_VIRT_HUGE: u64 = SELF_CHECK_SCRATCH_VIRT + 0x20_0000;
+const SELF_CHECK_SCRATCH_VIRT_IDENTITY: u64 = SELF_CHECK_SCRATCH_VIRT + 0x40_0000;
+
+/// Exercise every `Mapper`/`BitmapFrameAllocator` capability this module
+/// added — map, translate, unmap (both the plain and huge-page paths),
+/// `identity_map_range`, and `activate` — against scratch addresses nothing
+/// else uses, so a regression in any of them is caught at boot instead of
+/// sitting as untested, unreachable code.
+unsafe fn self_check(state: &mut PagingState) {
+    let frame = state
+        .allocator
+        .allocate_frame()
+        .expect("paging self-check: no free frame");
+    unsafe {
+        state
+            .mapper
+            .map_page(
+                SELF_CHECK_SCRATCH_VIRT,
+                frame,
+                flags::PRESENT | flags::WRITABLE,
+                false,
+                &mut state.allocator,
+            )
+            .expect("paging self-check: scratch address already mapped");
+    }
+
+    let ptr = SELF_CHECK_SCRATCH_VIRT as *mut u64;
+    unsafe {
+        ptr.write_volatile(0xA5A5_A5A5_A5A5_A5A5);
+        assert_eq!(
+            ptr.read_volatile(),
+            0xA5A5_A5A5_A5A5_A5A5,
+            "paging self-check: readback mismatch through a freshly mapped page"
+        );
+    }
+    assert_eq!(
+        unsafe { state.mapper.translate(SELF_CHECK_SCRATCH_VIRT) },
+        Some(frame),
+        "paging self-check: translate() disagrees with the mapping just created"
+    );
+    assert_eq!(
+        unsafe { state.mapper.unmap(SELF_CHECK_SCRATCH_VIRT) },
+        Some(frame),
+        "paging self-check: unmap() didn't return the mapped frame"
+    );
+    state.allocator.deallocate_frame(frame);
+
+    // Huge-page round trip: map_huge_page_2mib writes a HUGE_PAGE entry at
+    // the PD level, and unmap must clear that entry directly rather than
+    // (mis)walking its frame as if it were a PT — the bug this self-check
+    // guards against.
+    let huge_frame = state
+        .allocator
+        .allocate_frame()
+        .expect("paging self-check: no free frame (huge)")
+        & !0x1F_FFFF;
+    unsafe {
+        state
+            .mapper
+            .map_huge_page_2mib(
+                SELF_CHECK_SCRATCH_VIRT_HUGE,
+                huge_frame,
+                flags::PRESENT | flags::WRITABLE,
+                false,
+                &mut state.allocator,
+            )
+            .expect("paging self-check: huge scratch address already mapped");
+    }
+    assert_eq!(
+        unsafe { state.mapper.unmap(SELF_CHECK_SCRATCH_VIRT_HUGE) },
+        Some(huge_frame),
+        "paging self-check: unmap() didn't clear the huge entry directly"
+    );
+
+    // identity_map_range/unmap: never read or write through this one, since
+    // "phys == virt" here doesn't correspond to real backing memory — just
+    // confirm the range maps and then clears cleanly.
+    unsafe {
+        state.mapper.identity_map_range(
+            SELF_CHECK_SCRATCH_VIRT_IDENTITY,
+            SELF_CHECK_SCRATCH_VIRT_IDENTITY + FRAME_SIZE,
+            flags::PRESENT | flags::WRITABLE,
+            &mut state.allocator,
+        );
+    }
+    assert_eq!(
+        unsafe { state.mapper.unmap(SELF_CHECK_SCRATCH_VIRT_IDENTITY) },
+        Some(SELF_CHECK_SCRATCH_VIRT_IDENTITY),
+        "paging self-check: unmap() of an identity-mapped page returned the wrong frame"
+    );
+
+    // Re-assert the (unchanged) active page tables — on real hardware this
+    // is the same CR3 value already loaded, so it's a safe no-op TLB flush
+    // that still exercises the one capability that can't be driven any
+    // other way without tearing down the kernel's own mapping.
+    unsafe { state.mapper.activate() };
+}
+
+/// Hand out a physical frame from the pool `init` brought up.
+pub fn allocate_frame() -> Option<u64> {
+    PAGING.with(|state| {
+        state
+            .as_mut()
+            .expect("paging::init not called")
+            .allocator
+            .allocate_frame()
+    })
+}
+
+/// Return a frame previously handed out by `allocate_frame`.
+pub fn deallocate_frame(frame: u64) {
+    PAGING.with(|state| {
+        state
+            .as_mut()
+            .expect("paging::init not called")
+            .allocator
+            .deallocate_frame(frame)
+    });
+}
+
+/// Resolve `virt` to its physical address through the `Mapper` `init`
+/// brought up over the bootloader's active page tables.
+pub fn translate(virt: u64) -> Option<u64> {
+    PAGING.with(|state| unsafe {
+        state
+            .as_ref()
+            .expect("paging::init not called")
+            .mapper
+            .translate(virt)
+    })
+}
+
 fn pml4_index(addr: u64) -> usize {
     ((addr >> 39) & 0x1FF) as usize
 }
@@ -1,33 +1,97 @@
-//! Panic printer scaffolding.
+//! Panic printer: prints the panic location and message to VGA, the
+//! location's file name to serial, and a best-effort stack backtrace to
+//! both, then leaves halting to the caller's `#[panic_handler]` loop.
 //!
-//! Goal: print panic information (message, file:line, backtrace if any)
-//! to VGA and serial. Keep it simple and robust.
-//!
-//! Topics to read:
-//! - `core::panic::PanicInfo` API: message(), location()
-//! - Formatting without allocation, avoiding re-entrancy
-//! - Possibly disabling interrupts while printing
+//! The backtrace walks the saved-`rbp` chain left by standard
+//! `push rbp; mov rbp, rsp` prologues — at each frame the return address
+//! lives at `[rbp + 8]` and the caller's frame pointer at `[rbp]`. This
+//! only produces anything meaningful if the kernel is built with frame
+//! pointers retained (i.e. without `-C force-frame-pointers=no`).
 
+use core::arch::asm;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::println;
 
-/// Print panic info. You can call this from `#[panic_handler]`.
+/// Bound on how many frames we'll walk, so a corrupted or cyclic frame
+/// chain can't loop forever.
+const MAX_FRAMES: usize = 32;
+
+/// Set as soon as we start printing a panic. If printing itself panics,
+/// we land back here and halt immediately instead of recursing.
+static ALREADY_PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Print panic info plus a stack backtrace. Call from `#[panic_handler]`;
+/// the caller is responsible for halting afterwards.
 pub fn print(info: &PanicInfo) {
-    // Minimal safe printing; expand as needed.
+    x86_64::instructions::interrupts::disable();
+
+    if ALREADY_PANICKING.swap(true, Ordering::SeqCst) {
+        loop {
+            x86_64::instructions::hlt();
+        }
+    }
+
     if let Some(loc) = info.location() {
-        println!(
-            "KERNEL PANIC at {}:{}:{}",
-            loc.file(),
-            loc.line(),
-            loc.column()
-        );
-        crate::serial::write_str("KERNEL PANIC\n");
+        println!("KERNEL PANIC at {}:{}:{}", loc.file(), loc.line(), loc.column());
+        crate::serial::write_str("KERNEL PANIC at ");
+        crate::serial::write_str(loc.file());
+        crate::serial::write_str("\n");
     } else {
         println!("KERNEL PANIC at <unknown location>");
         crate::serial::write_str("KERNEL PANIC at <unknown location>\n");
     }
-    // Note: formatting via println! avoids heap.
+
     println!("message: {}", info.message());
     crate::serial::write_str("panic: see VGA for details\n");
-    // TODO: Optionally mirror to serial::write_str and add more context.
+
+    print_backtrace();
+}
+
+fn print_backtrace() {
+    println!("backtrace:");
+    crate::serial::write_str("backtrace:\n");
+
+    let mut rbp: u64;
+    unsafe {
+        asm!("mov {0}, rbp", out(reg) rbp, options(nostack, preserves_flags));
+    }
+
+    let mut previous_rbp = 0u64;
+    for frame in 0..MAX_FRAMES {
+        if rbp == 0 || rbp % 8 != 0 {
+            break;
+        }
+        // The chain should strictly climb towards higher addresses; if it
+        // doesn't, the stack is corrupt and walking further isn't safe.
+        if previous_rbp != 0 && rbp <= previous_rbp {
+            break;
+        }
+
+        let return_addr = unsafe { *((rbp + 8) as *const u64) };
+        let next_rbp = unsafe { *(rbp as *const u64) };
+
+        print_frame(frame, return_addr);
+
+        previous_rbp = rbp;
+        rbp = next_rbp;
+    }
+}
+
+fn print_frame(frame: usize, addr: u64) {
+    use crate::parser::uint_to_str_radix;
+
+    let mut addr_buf = [0u8; 20];
+    let addr_hex = uint_to_str_radix(addr, 16, &mut addr_buf, true).unwrap_or("<?>");
+
+    println!("  #{} {}", frame, addr_hex);
+
+    let mut frame_buf = [0u8; 8];
+    let frame_dec = uint_to_str_radix(frame as u64, 10, &mut frame_buf, false).unwrap_or("?");
+    crate::serial::write_str("  #");
+    crate::serial::write_str(frame_dec);
+    crate::serial::write_str(" ");
+    crate::serial::write_str(addr_hex);
+    crate::serial::write_str("\n");
 }
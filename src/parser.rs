@@ -1,23 +1,22 @@
-//! Simple parsing helpers for the toy OS shell.
+//! Parsing/formatting helpers for the toy OS shell.
 //!
-//! This module is intentionally left mostly unimplemented so you can
-//! practice writing conversion logic between textual user input and
-//! numeric data types.
-
-/// Parse an integer from an ASCII string slice.
-///
-/// # TODO
-/// * Decide whether to support optional `+`/`-` signs.
-/// * Handle decimal digits only (hex/bin support can come later).
-/// * Validate that every character is a digit before converting.
-/// * Return either the parsed integer or an error describing why parsing failed.
+//! Decimal is the default, but the shell also needs to round-trip I/O
+//! ports, addresses and error codes that print elsewhere in the kernel in
+//! hex/binary/octal — so `parse_int_from_str`, `parse_int_radix` and
+//! `uint_to_str_radix` accept a radix, either via an explicit argument or a
+//! `0x`/`0b`/`0o` prefix. `int_to_str_buf` and `eval_expr` stay decimal-only.
 
+/// Errors for both parsing and formatting below.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParseError {
     InvalidDigit,
     EmptyInput,
     InvalidSign,
     BufferTooSmall,
+    UnbalancedParens,
+    UnexpectedToken,
+    DivisionByZero,
+    Overflow,
 }
 
 impl ParseError {
@@ -27,12 +26,38 @@ impl ParseError {
             ParseError::EmptyInput => "input string is empty",
             ParseError::InvalidSign => "invalid sign placement",
             ParseError::BufferTooSmall => "buffer too small for conversion",
+            ParseError::UnbalancedParens => "unbalanced parentheses",
+            ParseError::UnexpectedToken => "unexpected token in expression",
+            ParseError::DivisionByZero => "division by zero",
+            ParseError::Overflow => "value out of range for a 64-bit signed integer",
         }
     }
 }
 
-pub fn parse_int_from_str(_s: &str) -> Result<i64, ParseError> {
-    let bytes = _s.as_bytes();
+/// Parse a signed integer, decimal by default but also recognizing a
+/// `0x`/`0b`/`0o` prefix (after an optional leading sign) for hex/binary/octal.
+pub fn parse_int_from_str(s: &str) -> Result<i64, ParseError> {
+    let bytes = s.as_bytes();
+
+    if bytes.is_empty() {
+        return Err(ParseError::EmptyInput);
+    }
+
+    let (sign, rest) = match bytes[0] {
+        b'+' => (1, &bytes[1..]),
+        b'-' => (-1, &bytes[1..]),
+        _ => (1, bytes),
+    };
+
+    let (radix, digits) = strip_radix_prefix(rest);
+    let value = parse_digits(digits, radix)?;
+    apply_sign(value, sign)
+}
+
+/// Parse a signed integer in an explicitly given `radix` (2..=16), with an
+/// optional leading sign but no `0x`/`0b`/`0o` prefix expected.
+pub fn parse_int_radix(s: &str, radix: u32) -> Result<i64, ParseError> {
+    let bytes = s.as_bytes();
 
     if bytes.is_empty() {
         return Err(ParseError::EmptyInput);
@@ -44,19 +69,68 @@ pub fn parse_int_from_str(_s: &str) -> Result<i64, ParseError> {
         _ => (1, bytes),
     };
 
+    let value = parse_digits(digits, radix)?;
+    apply_sign(value, sign)
+}
+
+/// Apply a parsed sign (`1` or `-1`) to the unsigned magnitude `parse_digits`
+/// accumulated, rejecting anything that wouldn't fit in an `i64` instead of
+/// silently wrapping (e.g. `u64::MAX` would otherwise cast to `-1`).
+fn apply_sign(value: u64, sign: i64) -> Result<i64, ParseError> {
+    if sign < 0 {
+        if value > i64::MIN.unsigned_abs() {
+            return Err(ParseError::Overflow);
+        }
+        if value == i64::MIN.unsigned_abs() {
+            return Ok(i64::MIN);
+        }
+        Ok(-(value as i64))
+    } else {
+        if value > i64::MAX as u64 {
+            return Err(ParseError::Overflow);
+        }
+        Ok(value as i64)
+    }
+}
+
+/// Strip a `0x`/`0b`/`0o` prefix, returning the radix it implies (decimal
+/// if none) and the remaining digit bytes.
+fn strip_radix_prefix(bytes: &[u8]) -> (u32, &[u8]) {
+    if bytes.len() >= 2 && bytes[0] == b'0' {
+        match bytes[1] {
+            b'x' | b'X' => return (16, &bytes[2..]),
+            b'b' | b'B' => return (2, &bytes[2..]),
+            b'o' | b'O' => return (8, &bytes[2..]),
+            _ => {}
+        }
+    }
+    (10, bytes)
+}
+
+fn digit_value(byte: u8) -> Option<u32> {
+    match byte {
+        b'0'..=b'9' => Some((byte - b'0') as u32),
+        b'a'..=b'z' => Some((byte - b'a') as u32 + 10),
+        b'A'..=b'Z' => Some((byte - b'A') as u32 + 10),
+        _ => None,
+    }
+}
+
+fn parse_digits(digits: &[u8], radix: u32) -> Result<u64, ParseError> {
     if digits.is_empty() {
         return Err(ParseError::InvalidDigit);
     }
 
-    let mut value: i64 = 0;
+    let mut value: u64 = 0;
     for &byte in digits {
-        if byte < b'0' || byte > b'9' {
-            return Err(ParseError::InvalidDigit);
-        }
-        value = value.checked_mul(10).and_then(|v| v.checked_add((byte - b'0') as i64)).ok_or(ParseError::InvalidDigit)?;
+        let digit = digit_value(byte).filter(|&d| d < radix).ok_or(ParseError::InvalidDigit)?;
+        value = value
+            .checked_mul(radix as u64)
+            .and_then(|v| v.checked_add(digit as u64))
+            .ok_or(ParseError::InvalidDigit)?;
     }
 
-    Ok(value * sign)
+    Ok(value)
 }
 
 /// Convert an integer back to its decimal ASCII representation.
@@ -94,3 +168,181 @@ pub fn int_to_str_buf(value: i64, buf: &mut [u8]) -> Result<&str, ParseError> {
     buf[..i].reverse();
     Ok(core::str::from_utf8(&buf[..i]).unwrap())
 }
+
+fn radix_digit(value: u32) -> u8 {
+    match value {
+        0..=9 => b'0' + value as u8,
+        _ => b'a' + (value - 10) as u8,
+    }
+}
+
+/// Write `value` in `radix` (2..=16) into `buf`, reversing in place the same
+/// way `int_to_str_buf` does. `with_prefix` adds the matching `0x`/`0b`/`0o`
+/// for radix 16/2/8 (no prefix exists for other bases, so it's ignored then).
+pub fn uint_to_str_radix(value: u64, radix: u32, buf: &mut [u8], with_prefix: bool) -> Result<&str, ParseError> {
+    let prefix: &[u8] = if with_prefix {
+        match radix {
+            16 => b"0x",
+            2 => b"0b",
+            8 => b"0o",
+            _ => b"",
+        }
+    } else {
+        b""
+    };
+
+    if value == 0 {
+        let total = prefix.len() + 1;
+        if total > buf.len() {
+            return Err(ParseError::BufferTooSmall);
+        }
+        buf[..prefix.len()].copy_from_slice(prefix);
+        buf[prefix.len()] = b'0';
+        return Ok(core::str::from_utf8(&buf[..total]).unwrap());
+    }
+
+    let mut n = value;
+    let mut i = 0;
+    while n > 0 {
+        if i >= buf.len() {
+            return Err(ParseError::BufferTooSmall);
+        }
+        buf[i] = radix_digit((n % radix as u64) as u32);
+        n /= radix as u64;
+        i += 1;
+    }
+
+    buf[..i].reverse();
+
+    if prefix.is_empty() {
+        return Ok(core::str::from_utf8(&buf[..i]).unwrap());
+    }
+
+    if prefix.len() + i > buf.len() {
+        return Err(ParseError::BufferTooSmall);
+    }
+    buf.copy_within(0..i, prefix.len());
+    buf[..prefix.len()].copy_from_slice(prefix);
+    Ok(core::str::from_utf8(&buf[..prefix.len() + i]).unwrap())
+}
+
+/// Evaluate a `+ - * /` arithmetic expression with unary minus and
+/// parentheses, classic recursive-descent over a byte cursor:
+/// `expr := term (('+'|'-') term)*`
+/// `term := factor (('*'|'/') factor)*`
+/// `factor := number | '(' expr ')' | '-' factor`
+///
+/// Bounded by the caller's input length (shell commands top out at
+/// `INPUT_BUFFER_LEN`), so the recursion here never runs deep enough to
+/// threaten the stack.
+pub fn eval_expr(input: &str) -> Result<i64, ParseError> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err(ParseError::EmptyInput);
+    }
+
+    let mut cursor = ExprCursor { bytes: trimmed.as_bytes(), pos: 0 };
+    let value = cursor.parse_expr()?;
+    cursor.skip_whitespace();
+    if cursor.pos != cursor.bytes.len() {
+        return Err(ParseError::UnexpectedToken);
+    }
+    Ok(value)
+}
+
+struct ExprCursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ExprCursor<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos] == b' ' {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn parse_expr(&mut self) -> Result<i64, ParseError> {
+        let mut value = self.parse_term()?;
+        loop {
+            match self.peek() {
+                Some(b'+') => {
+                    self.pos += 1;
+                    value = value.wrapping_add(self.parse_term()?);
+                }
+                Some(b'-') => {
+                    self.pos += 1;
+                    value = value.wrapping_sub(self.parse_term()?);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_term(&mut self) -> Result<i64, ParseError> {
+        let mut value = self.parse_factor()?;
+        loop {
+            match self.peek() {
+                Some(b'*') => {
+                    self.pos += 1;
+                    value = value.wrapping_mul(self.parse_factor()?);
+                }
+                Some(b'/') => {
+                    self.pos += 1;
+                    let rhs = self.parse_factor()?;
+                    if rhs == 0 {
+                        return Err(ParseError::DivisionByZero);
+                    }
+                    value = value.wrapping_div(rhs);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_factor(&mut self) -> Result<i64, ParseError> {
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(-self.parse_factor()?)
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let value = self.parse_expr()?;
+                match self.peek() {
+                    Some(b')') => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err(ParseError::UnbalancedParens),
+                }
+            }
+            Some(byte) if byte == b'+' || byte.is_ascii_digit() => self.parse_number(),
+            _ => Err(ParseError::UnexpectedToken),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<i64, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.bytes.get(self.pos) == Some(&b'+') {
+            self.pos += 1;
+        }
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(ParseError::UnexpectedToken);
+        }
+        let slice = core::str::from_utf8(&self.bytes[start..self.pos])
+            .map_err(|_| ParseError::UnexpectedToken)?;
+        parse_int_from_str(slice)
+    }
+}
@@ -0,0 +1,187 @@
+//! Preemptive round-robin task scheduler, driven by the timer interrupt.
+//!
+//! Each [`Task`] owns a dedicated heap-allocated kernel stack (the heap
+//! subsystem must already be initialized). The ready list is a plain
+//! `Vec<Task>` — round-robin just walks it in a circle. Switching saves the
+//! outgoing task's RFLAGS, callee-saved registers (`rbx`/`rbp`/`r12`-`r15`),
+//! and stack pointer and restores the next task's via a tiny `asm!` trampoline;
+//! the `call`/`ret` pair does the rest of the work, since a task resumes
+//! exactly where `context_switch` left off — either inside this module (for
+//! a task that's run before) or at its `entry` function (the first time).
+//!
+//! `init` must run before any task is spawned: it registers whichever
+//! context calls it (the kernel's boot thread) as task 0, so there's always
+//! something to switch back to once every spawned task has had a turn.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::sync::SpinLock;
+
+/// Kernel stack size for a spawned task.
+const STACK_SIZE: usize = 16 * 1024;
+
+pub struct Task {
+    stack_ptr: u64,
+    /// Kept alive for as long as the task exists; never read directly,
+    /// only jumped into via `stack_ptr`.
+    _stack: Box<[u8]>,
+}
+
+impl Task {
+    /// Build a task whose first switch-in `ret`s straight into `entry`.
+    /// `entry` must not return — loop forever (e.g. `hlt` in a loop) as
+    /// there's nothing to return to.
+    fn new(entry: fn() -> !) -> Self {
+        let mut stack = alloc::vec![0u8; STACK_SIZE].into_boxed_slice();
+        let top = unsafe { stack.as_mut_ptr().add(STACK_SIZE) as u64 } & !0xF;
+
+        let mut sp = top;
+        let mut push = |value: u64| {
+            sp -= 8;
+            unsafe { (sp as *mut u64).write(value) };
+        };
+        // Order mirrors context_switch's push sequence (rflags, then rbx
+        // first ... r15 last), so its pop sequence (r15 first ... rbx
+        // last, then popfq) restores these, then `ret` jumps to `entry`.
+        // RFLAGS = 0x202: reserved bit 1 plus IF — a freshly spawned task
+        // must start with interrupts enabled, or the first `ret` into
+        // `entry` runs with whatever IF was current at the `call
+        // context_switch` site (0, since `preempt` calls in from inside an
+        // interrupt-gate handler), permanently wedging interrupts off.
+        const INITIAL_RFLAGS: u64 = 0x202;
+        push(entry as usize as u64);
+        push(INITIAL_RFLAGS);
+        push(0); // rbx
+        push(0); // rbp
+        push(0); // r12
+        push(0); // r13
+        push(0); // r14
+        push(0); // r15
+
+        Self {
+            stack_ptr: sp,
+            _stack: stack,
+        }
+    }
+
+    /// Stands in for whatever context calls `init` — its real stack pointer
+    /// is filled in by the first `context_switch` away from it.
+    fn current_context() -> Self {
+        Self {
+            stack_ptr: 0,
+            _stack: Box::new([]),
+        }
+    }
+}
+
+struct Scheduler {
+    tasks: Vec<Task>,
+    current: usize,
+}
+
+impl Scheduler {
+    const fn empty() -> Self {
+        Self {
+            tasks: Vec::new(),
+            current: 0,
+        }
+    }
+
+    fn ensure_current_task(&mut self) {
+        if self.tasks.is_empty() {
+            self.tasks.push(Task::current_context());
+        }
+    }
+
+    fn spawn(&mut self, entry: fn() -> !) {
+        self.ensure_current_task();
+        self.tasks.push(Task::new(entry));
+    }
+
+    /// Round-robin pick of the next task. Returns the outgoing task's saved
+    /// stack-pointer slot and the incoming task's stack pointer, or `None`
+    /// if there's nobody else to switch to.
+    fn next_switch(&mut self) -> Option<(*mut u64, u64)> {
+        if self.tasks.len() < 2 {
+            return None;
+        }
+        let prev = self.current;
+        self.current = (self.current + 1) % self.tasks.len();
+        let prev_ptr = &mut self.tasks[prev].stack_ptr as *mut u64;
+        let next_sp = self.tasks[self.current].stack_ptr;
+        Some((prev_ptr, next_sp))
+    }
+}
+
+static SCHEDULER: SpinLock<Scheduler> = SpinLock::new(Scheduler::empty());
+
+/// Register the calling context as task 0. Call once, before `spawn`ing
+/// anything, from a context you're happy to have round-robin away from
+/// (typically the kernel's idle/boot thread).
+pub fn init() {
+    SCHEDULER.with(Scheduler::ensure_current_task);
+}
+
+/// Add a new task to the round-robin ready list.
+pub fn spawn(entry: fn() -> !) {
+    SCHEDULER.with(|sched| sched.spawn(entry));
+}
+
+/// Called from `timer_interrupt_handler` after EOI: preempt the current
+/// task in favor of the next one, round-robin.
+pub fn preempt() {
+    switch();
+}
+
+/// Cooperative yield: give up the rest of this task's timeslice immediately
+/// instead of waiting for the next timer tick.
+pub fn yield_now() {
+    switch();
+}
+
+fn switch() {
+    let Some((prev_ptr, next_sp)) = SCHEDULER.with(Scheduler::next_switch) else {
+        return;
+    };
+    // Safety: `prev_ptr` points at a live `Task::stack_ptr` slot in
+    // `SCHEDULER`'s task list; `next_sp` came from a previous `context_switch`
+    // save or from `Task::new`'s synthetic initial frame.
+    unsafe { context_switch(prev_ptr, next_sp) };
+}
+
+/// Save the caller's RFLAGS, callee-saved registers, and stack pointer to
+/// `*save_sp`, then load `new_sp` and restore its RFLAGS/callee-saved
+/// registers before `ret`-ing into whatever address is next on that stack —
+/// either back into a previous `context_switch` call, or into a fresh
+/// task's `entry` function.
+///
+/// RFLAGS (in particular IF) must round-trip through here explicitly: a
+/// task resuming through its own earlier interrupt frame would get IF
+/// restored by that frame's `iretq`, but a task switched in via a plain
+/// `ret` — which is every task, every time it's rescheduled — has no such
+/// frame, so without `pushfq`/`popfq` here IF would freeze at whatever it
+/// was at the `call context_switch` site (0, since `preempt` calls in from
+/// inside an interrupt-gate handler) for the rest of that task's life.
+#[unsafe(naked)]
+unsafe extern "C" fn context_switch(save_sp: *mut u64, new_sp: u64) {
+    core::arch::naked_asm!(
+        "pushfq",
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "popfq",
+        "ret",
+    )
+}
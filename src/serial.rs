@@ -1,16 +1,23 @@
 //! Serial (COM1) driver for early diagnostics/logging.
 //!
 //! Что делает модуль:
-//! - Инициализирует 16550‑совместимый UART на COM1 (0x3F8) под 115200 8N1.
+//! - Инициализирует 16550‑совместимый UART на COM1 (0x3F8).
 //! - Даёт блокирующую запись байта и строки (поллинг по LSR.THR_EMPTY).
+//! - Принимает байты по IRQ4 в lock-free SPSC кольцевой буфер, так что
+//!   `read_byte`/`try_read_byte` не зависят от того, успели ли мы
+//!   забрать байт из RBR до следующего символа.
+//! - Позволяет перенастроить скорость/формат кадра через `configure`.
 //!
 //! Что почитать, чтобы понимать код:
-//! - 16550 UART: регистры LCR/IER/FCR/MCR/LSR и бит DLAB.
-//! - Делитель скорости: базовая тактовая 1_843_200/16 = 115_200 бод; divisor=115_200/baud.
+//! - 16550 UART: регистры LCR/IER/FCR/MCR/LSR/IIR и бит DLAB.
+//! - Делитель скорости: базовая тактовая 115_200 Гц; divisor=115_200/baud.
 //! - Порты COM1: база 0x3F8; смещения DLL/DLM/LCR/LSR и др.
 
 #![allow(dead_code)]
 
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 // COM1 base I/O port
 const COM1: u16 = 0x3F8;
 
@@ -21,6 +28,7 @@ const FCR_IIR: u16 = 2;     // FIFO Control / Interrupt Identification (read)
 const LCR: u16 = 3;         // Line Control
 const MCR: u16 = 4;         // Modem Control
 const LSR: u16 = 5;         // Line Status
+const MSR: u16 = 6;         // Modem Status
 
 // LCR bits
 const LCR_WORDLEN_8: u8 = 0b11; // 8 data bits
@@ -28,38 +36,174 @@ const LCR_STOP_1: u8 = 0 << 2; // 1 stop bit
 const LCR_PARITY_NONE: u8 = 0 << 3;
 const LCR_DLAB: u8 = 1 << 7; // Divisor Latch Access Bit
 
+// IER bits
+const IER_RX_DATA_AVAILABLE: u8 = 1 << 0;
+
 // LSR bits
+const LSR_DATA_READY: u8 = 1 << 0; // Receive Buffer Register has a byte
+const LSR_OVERRUN_ERROR: u8 = 1 << 1;
+const LSR_PARITY_ERROR: u8 = 1 << 2;
+const LSR_FRAMING_ERROR: u8 = 1 << 3;
+const LSR_BREAK_INTERRUPT: u8 = 1 << 4;
 const LSR_THR_EMPTY: u8 = 1 << 5; // Transmitter Holding Register Empty
 
-/// Инициализация COM1 на 115200 8N1, включение FIFO, MCR: DTR|RTS|OUT2.
+// IIR "interrupt pending" + cause bits (bit 0 clear means an interrupt is pending).
+// Cause occupies bits [3:1]; 110 (char timeout) only appears with FIFOs enabled.
+const IIR_NO_INTERRUPT_PENDING: u8 = 1 << 0;
+const IIR_CAUSE_MODEM_STATUS: u8 = 0b000;
+const IIR_CAUSE_TX_EMPTY: u8 = 0b001;
+const IIR_CAUSE_RX_DATA: u8 = 0b010;
+const IIR_CAUSE_LINE_STATUS: u8 = 0b011;
+const IIR_CAUSE_CHAR_TIMEOUT: u8 = 0b110;
+
+const BASE_CLOCK_HZ: u32 = 115_200;
+
+const RX_QUEUE_CAPACITY: usize = 256;
+
+/// Lock-free single-producer/single-consumer ring buffer: the IRQ4 handler
+/// is the only producer, `read_byte`/`try_read_byte` the only consumer.
+struct RxRingBuffer {
+    buf: UnsafeCell<[u8; RX_QUEUE_CAPACITY]>,
+    head: AtomicUsize, // next slot the producer will write
+    tail: AtomicUsize, // next slot the consumer will read
+}
+
+unsafe impl Sync for RxRingBuffer {}
+
+impl RxRingBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: UnsafeCell::new([0; RX_QUEUE_CAPACITY]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Called from `handle_irq`. Drops the byte if the buffer is full.
+    fn push(&self, byte: u8) {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % RX_QUEUE_CAPACITY;
+        if next == self.tail.load(Ordering::Acquire) {
+            return; // full: reader isn't keeping up, drop the byte
+        }
+        unsafe { (*self.buf.get())[head] = byte };
+        self.head.store(next, Ordering::Release);
+    }
+
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None; // empty
+        }
+        let byte = unsafe { (*self.buf.get())[tail] };
+        self.tail.store((tail + 1) % RX_QUEUE_CAPACITY, Ordering::Release);
+        Some(byte)
+    }
+}
+
+static RX_QUEUE: RxRingBuffer = RxRingBuffer::new();
+
+/// Number of data bits per frame, as programmed into LCR bits [1:0].
+#[derive(Clone, Copy)]
+pub enum WordLength {
+    Five = 0b00,
+    Six = 0b01,
+    Seven = 0b10,
+    Eight = 0b11,
+}
+
+/// Number of stop bits, LCR bit 2.
+#[derive(Clone, Copy)]
+pub enum StopBits {
+    One = 0,
+    Two = 1,
+}
+
+/// Parity mode, LCR bits [5:3].
+#[derive(Clone, Copy)]
+pub enum Parity {
+    None = 0b000,
+    Odd = 0b001,
+    Even = 0b011,
+    Mark = 0b101,
+    Space = 0b111,
+}
+
+/// Bits of LSR that indicate link corruption rather than normal flow.
+pub struct LineStatus {
+    pub overrun_error: bool,
+    pub parity_error: bool,
+    pub framing_error: bool,
+    pub break_interrupt: bool,
+}
+
+/// Инициализация COM1 на 115200 8N1, включение FIFO, MCR: DTR|RTS|OUT2,
+/// и разрешение прерывания "Received Data Available" (IRQ4).
 ///
 /// Порядок инициализации (важно):
 /// 1) Отключить UART‑прерывания (IER=0)
 /// 2) Установить DLAB=1, выставить делитель DLL/DLM
 /// 3) Убрать DLAB, включить 8N1 (LCR=0x03)
 /// 4) Включить FIFO и очистить очереди (FCR=0xC7)
-/// 5) MCR=0x0B (DTR|RTS|OUT2) – OUT2 нужно, если когда‑нибудь включим IRQ
+/// 5) MCR=0x0B (DTR|RTS|OUT2) – OUT2 нужен, чтобы IRQ4 реально доходил до PIC
+/// 6) IER=0x01 – включить приём по прерыванию
 pub fn init_unsafe_16550_default() {
+    configure(115_200, WordLength::Eight, StopBits::One, Parity::None);
     unsafe {
-        use crate::interrupts::{inb, outb};
-        let _ = inb; // suppress unused warnings on some platforms
+        use crate::interrupts::outb;
+        outb(COM1 + MCR, 0x0B);
+        outb(COM1 + IER_DLM, IER_RX_DATA_AVAILABLE);
+    }
+}
+
+/// Reprogram the baud-rate divisor and frame format. Keeps FIFOs enabled at
+/// the default 14-byte trigger level; call `set_fifo_trigger_level` after
+/// this if a different trigger is needed.
+pub fn configure(baud: u32, word_len: WordLength, stop_bits: StopBits, parity: Parity) {
+    let divisor = core::cmp::max(1, core::cmp::min(0xFFFF, BASE_CLOCK_HZ / core::cmp::max(1, baud)));
+    let lcr = (word_len as u8) | ((stop_bits as u8) << 2) | ((parity as u8) << 3);
+
+    unsafe {
+        use crate::interrupts::outb;
 
-        // 1) Disable UART interrupts
         outb(COM1 + IER_DLM, 0x00);
 
-        // 2) Enable DLAB and program divisor for 115200 baud -> divisor = 1
         outb(COM1 + LCR, LCR_DLAB);
-        outb(COM1 + RBR_THR_DLL, 0x01); // DLL
-        outb(COM1 + IER_DLM, 0x00);     // DLM
-
-        // 3) 8 data bits, 1 stop, no parity (DLAB=0)
-        outb(COM1 + LCR, LCR_WORDLEN_8 | LCR_STOP_1 | LCR_PARITY_NONE); // 0x03
+        outb(COM1 + RBR_THR_DLL, (divisor & 0xFF) as u8);
+        outb(COM1 + IER_DLM, (divisor >> 8) as u8);
 
-        // 4) Enable FIFO, clear RX/TX, trigger level 14 bytes
+        outb(COM1 + LCR, lcr);
         outb(COM1 + FCR_IIR, 0xC7);
+    }
+}
 
-        // 5) Modem Control: DTR | RTS | OUT2
-        outb(COM1 + MCR, 0x0B);
+/// FIFO trigger level (bits [7:6] of FCR), i.e. how many received bytes
+/// accumulate before an RX-available interrupt fires.
+#[derive(Clone, Copy)]
+pub enum FifoTrigger {
+    Bytes1 = 0b00,
+    Bytes4 = 0b01,
+    Bytes8 = 0b10,
+    Bytes14 = 0b11,
+}
+
+/// Re-enable FIFOs (clearing both queues) at the given trigger level.
+pub fn set_fifo_trigger_level(level: FifoTrigger) {
+    unsafe {
+        use crate::interrupts::outb;
+        outb(COM1 + FCR_IIR, ((level as u8) << 6) | 0b0000_0111);
+    }
+}
+
+/// Snapshot of the error bits in LSR. Reading LSR clears them on real
+/// hardware, so this is the only reliable way to observe a break/overrun.
+pub fn line_status() -> LineStatus {
+    let lsr = unsafe { crate::interrupts::inb(COM1 + LSR) };
+    LineStatus {
+        overrun_error: lsr & LSR_OVERRUN_ERROR != 0,
+        parity_error: lsr & LSR_PARITY_ERROR != 0,
+        framing_error: lsr & LSR_FRAMING_ERROR != 0,
+        break_interrupt: lsr & LSR_BREAK_INTERRUPT != 0,
     }
 }
 
@@ -86,3 +230,117 @@ pub fn write_str(s: &str) {
 pub fn is_transmit_empty() -> bool {
     unsafe { (crate::interrupts::inb(COM1 + LSR) & LSR_THR_EMPTY) != 0 }
 }
+
+/// Called from the IRQ4 handler. Drains every cause IIR reports this pass,
+/// since the FIFO trigger level and char-timeout cause can both leave more
+/// than one reason pending at once.
+pub fn handle_irq() {
+    unsafe {
+        use crate::interrupts::inb;
+        loop {
+            let iir = inb(COM1 + FCR_IIR);
+            if iir & IIR_NO_INTERRUPT_PENDING != 0 {
+                break;
+            }
+            match (iir >> 1) & 0b111 {
+                IIR_CAUSE_RX_DATA | IIR_CAUSE_CHAR_TIMEOUT => {
+                    RX_QUEUE.push(inb(COM1 + RBR_THR_DLL));
+                }
+                IIR_CAUSE_LINE_STATUS => {
+                    let _ = line_status();
+                }
+                IIR_CAUSE_TX_EMPTY => {
+                    // Nothing queued to transmit asynchronously yet; reading
+                    // IIR above already acknowledged the cause.
+                }
+                IIR_CAUSE_MODEM_STATUS => {
+                    let _ = inb(COM1 + MSR);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Pop the next received byte, blocking until IRQ4 delivers one.
+pub fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = RX_QUEUE.pop() {
+            return byte;
+        }
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Pop the next received byte without blocking.
+pub fn try_read_byte() -> Option<u8> {
+    RX_QUEUE.pop()
+}
+
+/// Блокирующее чтение байта напрямую из RBR, в обход кольцевого буфера.
+///
+/// Полезно только пока прерывания COM1 ещё не разрешены (ранний бут) —
+/// как только `handle_irq` начинает вытаскивать байты по IRQ4, этот поллинг
+/// будет соревноваться с ISR за один и тот же байт. Предпочитай `read_byte`.
+pub fn read_byte_blocking() -> u8 {
+    unsafe {
+        use crate::interrupts::inb;
+        while inb(COM1 + LSR) & LSR_DATA_READY == 0 {}
+        inb(COM1 + RBR_THR_DLL)
+    }
+}
+
+/// Zero-sized `core::fmt::Write` adapter over `write_str`, so panic output
+/// and shell diagnostics can be formatted straight onto the UART the same
+/// way `vga_buffer`'s `WRITER` formats onto the screen.
+struct SerialWriter;
+
+impl core::fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
+
+struct GlobalSerialWriter(UnsafeCell<SerialWriter>);
+
+impl GlobalSerialWriter {
+    const fn new() -> Self {
+        Self(UnsafeCell::new(SerialWriter))
+    }
+
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut SerialWriter) -> R,
+    {
+        x86_64::instructions::interrupts::without_interrupts(|| unsafe { f(&mut *self.0.get()) })
+    }
+}
+
+unsafe impl Sync for GlobalSerialWriter {}
+
+static SERIAL_WRITER: GlobalSerialWriter = GlobalSerialWriter::new();
+
+/// Used by the `serial_print!`/`serial_println!` macros below; not meant
+/// to be called directly.
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+    SERIAL_WRITER.with(|writer| {
+        let _ = writer.write_fmt(args);
+    });
+}
+
+/// Formats onto the serial port the way `print!` formats onto VGA — so
+/// panic output and shell diagnostics reach QEMU's `-serial stdio` even
+/// when the VGA console isn't visible.
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(core::format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", core::format_args!($($arg)*)));
+}
@@ -1,32 +1,189 @@
 use crate::{
     history::InputHistory,
     keyboard,
-    parser::{int_to_str_buf, parse_int_from_str, ParseError},
+    parser::{self, int_to_str_buf, parse_int_from_str, uint_to_str_radix, ParseError},
+    serial,
     simple_string::FixedString,
     vga_buffer::{self, get_color_code, Color},
+    vm::{self, Vm},
 };
 use core::hint::spin_loop;
 
 const INPUT_BUFFER_LEN: usize = 128;
 
+/// Control byte values used by both `CharSource` impls below to report
+/// arrow-key history navigation through the same `Option<u8>` stream as
+/// ordinary input bytes. The keyboard decoder never produces them, but a
+/// serial terminal sends them for Ctrl-A/Ctrl-B, which `SerialSource` passes
+/// through unchanged, so those keys also navigate history.
+const HISTORY_UP: u8 = 0x01;
+const HISTORY_DOWN: u8 = 0x02;
+const TAB: u8 = 0x09;
+
+/// Where the shell reads its next input byte from. A `KeyboardSource`
+/// decodes PS/2 scancodes (collapsing the `0xE0` extended prefix for arrow
+/// keys into [`HISTORY_UP`]/[`HISTORY_DOWN`]); a `SerialSource` decodes raw
+/// bytes off the 16550 UART the same way a terminal emulator would (CR,
+/// backspace, ANSI arrow escapes). Either can drive the same `Shell`, so it
+/// runs identically with a real keyboard+VGA session or headless under
+/// `QEMU -nographic`.
+pub trait CharSource {
+    fn next_byte(&mut self) -> Option<u8>;
+}
+
+pub struct KeyboardSource;
+
+impl KeyboardSource {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CharSource for KeyboardSource {
+    fn next_byte(&mut self) -> Option<u8> {
+        match keyboard::next_key()? {
+            keyboard::DecodedKey::Unicode(ch) => Some(ch as u8),
+            keyboard::DecodedKey::RawKey(keyboard::KeyCode::ArrowUp) => Some(HISTORY_UP),
+            keyboard::DecodedKey::RawKey(keyboard::KeyCode::ArrowDown) => Some(HISTORY_DOWN),
+        }
+    }
+}
+
+enum EscapeState {
+    None,
+    SawEsc,
+    SawBracket,
+}
+
+pub struct SerialSource {
+    escape: EscapeState,
+}
+
+impl SerialSource {
+    pub fn new() -> Self {
+        Self { escape: EscapeState::None }
+    }
+}
+
+impl CharSource for SerialSource {
+    fn next_byte(&mut self) -> Option<u8> {
+        loop {
+            let byte = serial::try_read_byte()?;
+
+            match self.escape {
+                EscapeState::None => {
+                    if byte == 0x1B {
+                        self.escape = EscapeState::SawEsc;
+                        continue;
+                    }
+                }
+                EscapeState::SawEsc => {
+                    self.escape = if byte == b'[' { EscapeState::SawBracket } else { EscapeState::None };
+                    continue;
+                }
+                EscapeState::SawBracket => {
+                    self.escape = EscapeState::None;
+                    match byte {
+                        b'A' => return Some(HISTORY_UP),
+                        b'B' => return Some(HISTORY_DOWN),
+                        _ => continue,
+                    }
+                }
+            }
+
+            return Some(match byte {
+                0x0D => b'\n',
+                0x7F => 0x08,
+                other => other,
+            });
+        }
+    }
+}
+
 pub struct Shell {
     buffer: [u8; INPUT_BUFFER_LEN],
     len: usize,
-    extended_prefix: bool,
     history: InputHistory,
     saved_line: FixedString<INPUT_BUFFER_LEN>,
     saved_line_active: bool,
 }
 
-enum CommandToExecute<'a> {
-    Greet { name: &'a str },
-    Sum { a: i64, b: i64 },
-    Diff { a: i64, b: i64 },
-    Min { a: i64, b: i64 },
-    Max { a: i64, b: i64 },
-    Exit,
+/// A single row of the command table: matched by `name`, listed by `help`
+/// (via the `help` command and TAB completion), and dispatched to
+/// `handler` once at least `arity` whitespace-separated arguments follow
+/// the command name. `handler` gets the raw remainder of the line after
+/// the command name and re-parses whatever shape it needs — this keeps
+/// every command's signature uniform so the table can stay data, not code.
+struct CommandDescriptor {
+    name: &'static str,
+    help: &'static str,
+    arity: usize,
+    handler: fn(&mut Shell, &str) -> CommandResult,
 }
 
+const COMMANDS: &[CommandDescriptor] = &[
+    CommandDescriptor {
+        name: "greet",
+        help: "greet [name] - print a greeting",
+        arity: 0,
+        handler: cmd_greet,
+    },
+    CommandDescriptor {
+        name: "sum",
+        help: "sum <a> <b> - print a + b",
+        arity: 2,
+        handler: cmd_sum,
+    },
+    CommandDescriptor {
+        name: "diff",
+        help: "diff <a> <b> - print a - b",
+        arity: 2,
+        handler: cmd_diff,
+    },
+    CommandDescriptor {
+        name: "min",
+        help: "min <a> <b> - print the smaller of a and b",
+        arity: 2,
+        handler: cmd_min,
+    },
+    CommandDescriptor {
+        name: "max",
+        help: "max <a> <b> - print the larger of a and b",
+        arity: 2,
+        handler: cmd_max,
+    },
+    CommandDescriptor {
+        name: "run",
+        help: "run <addr> - execute the VM demo program starting at <addr>",
+        arity: 1,
+        handler: cmd_run,
+    },
+    CommandDescriptor {
+        name: "eval",
+        help: "eval <expr> - evaluate an arithmetic expression",
+        arity: 1,
+        handler: cmd_eval,
+    },
+    CommandDescriptor {
+        name: "hash",
+        help: "hash <text> - print the FNV-1a hash of <text> in hex",
+        arity: 1,
+        handler: cmd_hash,
+    },
+    CommandDescriptor {
+        name: "help",
+        help: "help - list available commands",
+        arity: 0,
+        handler: cmd_help,
+    },
+    CommandDescriptor {
+        name: "exit",
+        help: "exit - halt the shell",
+        arity: 0,
+        handler: cmd_exit,
+    },
+];
+
 enum CommandError {
     UnknownCommand,
     InvalidArguments,
@@ -45,52 +202,154 @@ enum HistoryKey {
     Down,
 }
 
-fn command_parser<'a>(input: &'a str) -> Result<CommandToExecute<'a>, CommandError> {
-    let mut parts = input.trim().split_whitespace();
-    let cmd = parts.next().ok_or(CommandError::UnknownCommand)?;
+/// Split `line` into a command name and the raw remainder, look it up in
+/// [`COMMANDS`], and dispatch once it has at least as many whitespace-
+/// separated arguments as the descriptor's `arity` requires.
+fn dispatch_command(shell: &mut Shell, line: &str) -> CommandResult {
+    let mut split = line.splitn(2, char::is_whitespace);
+    let name = match split.next() {
+        Some(name) if !name.is_empty() => name,
+        _ => return CommandResult::Error(CommandError::UnknownCommand),
+    };
+    let rest = split.next().unwrap_or("").trim_start();
+
+    let command = match COMMANDS.iter().find(|cmd| cmd.name == name) {
+        Some(command) => command,
+        None => return CommandResult::Error(CommandError::UnknownCommand),
+    };
+
+    if rest.split_whitespace().count() < command.arity {
+        return CommandResult::Error(CommandError::InvalidArguments);
+    }
 
-    match cmd {
-        "greet" => {
-            let name = parts.next().unwrap_or("stranger");
-            Ok(CommandToExecute::Greet { name })
-        }
-        "sum" => {
-            let a_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let b_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let a = parse_int_from_str(a_str).map_err(CommandError::Parse)?;
-            let b = parse_int_from_str(b_str).map_err(CommandError::Parse)?;
-            Ok(CommandToExecute::Sum { a, b })
-        }
-        "diff" => {
-            let a_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let b_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let a = parse_int_from_str(a_str).map_err(CommandError::Parse)?;
-            let b = parse_int_from_str(b_str).map_err(CommandError::Parse)?;
-            Ok(CommandToExecute::Diff { a, b })
-        }
-        "min" => {
-            let a_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let b_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let a = parse_int_from_str(a_str).map_err(CommandError::Parse)?;
-            let b = parse_int_from_str(b_str).map_err(CommandError::Parse)?;
-            Ok(CommandToExecute::Min { a, b })
-        }
-        "max" => {
-            let a_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let b_str = parts.next().ok_or(CommandError::InvalidArguments)?;
-            let a = parse_int_from_str(a_str).map_err(CommandError::Parse)?;
-            let b = parse_int_from_str(b_str).map_err(CommandError::Parse)?;
-            Ok(CommandToExecute::Max { a, b })
-        }
-        "exit" => Ok(CommandToExecute::Exit),
-        _ => Err(CommandError::UnknownCommand),
+    (command.handler)(shell, rest)
+}
+
+fn parse_two_ints(rest: &str) -> Result<(i64, i64), CommandError> {
+    let mut parts = rest.split_whitespace();
+    let a_str = parts.next().ok_or(CommandError::InvalidArguments)?;
+    let b_str = parts.next().ok_or(CommandError::InvalidArguments)?;
+    let a = parse_int_from_str(a_str).map_err(CommandError::Parse)?;
+    let b = parse_int_from_str(b_str).map_err(CommandError::Parse)?;
+    Ok((a, b))
+}
+
+fn print_i64_result(shell: &Shell, value: i64) -> CommandResult {
+    let mut tmp_buf = [0u8; 32];
+    match int_to_str_buf(value, &mut tmp_buf) {
+        Ok(output) => print_command_output(output),
+        Err(error) => shell.print_error(error.as_str()),
+    }
+    print_command_output("\n");
+    CommandResult::Success
+}
+
+fn cmd_greet(_shell: &mut Shell, rest: &str) -> CommandResult {
+    let name = rest.split_whitespace().next().unwrap_or("stranger");
+    let mut msg = FixedString::<64>::new();
+    let _ = msg.push_str("Hello, ");
+    let _ = msg.push_str(name);
+    let _ = msg.push_str("!\n");
+    print_command_output(msg.as_str());
+    CommandResult::Success
+}
+
+fn cmd_sum(shell: &mut Shell, rest: &str) -> CommandResult {
+    match parse_two_ints(rest) {
+        Ok((a, b)) => print_i64_result(shell, a + b),
+        Err(err) => CommandResult::Error(err),
+    }
+}
+
+fn cmd_diff(shell: &mut Shell, rest: &str) -> CommandResult {
+    match parse_two_ints(rest) {
+        Ok((a, b)) => print_i64_result(shell, a - b),
+        Err(err) => CommandResult::Error(err),
+    }
+}
+
+fn cmd_min(shell: &mut Shell, rest: &str) -> CommandResult {
+    match parse_two_ints(rest) {
+        Ok((a, b)) => print_i64_result(shell, core::cmp::min(a, b)),
+        Err(err) => CommandResult::Error(err),
+    }
+}
+
+fn cmd_max(shell: &mut Shell, rest: &str) -> CommandResult {
+    match parse_two_ints(rest) {
+        Ok((a, b)) => print_i64_result(shell, core::cmp::max(a, b)),
+        Err(err) => CommandResult::Error(err),
+    }
+}
+
+fn cmd_run(_shell: &mut Shell, rest: &str) -> CommandResult {
+    let addr_str = match rest.split_whitespace().next() {
+        Some(addr_str) => addr_str,
+        None => return CommandResult::Error(CommandError::InvalidArguments),
+    };
+    let addr = match parse_int_from_str(addr_str) {
+        Ok(addr) => addr as u64,
+        Err(err) => return CommandResult::Error(CommandError::Parse(err)),
+    };
+
+    let mut machine = Vm::new(vm::demo_program());
+    let trap = machine.run_from(addr as usize);
+    let mut msg = [0u8; 64];
+    print_command_output(trap.describe(&mut msg));
+    print_command_output("\n");
+    CommandResult::Success
+}
+
+fn cmd_eval(shell: &mut Shell, rest: &str) -> CommandResult {
+    match parser::eval_expr(rest) {
+        Ok(value) => print_i64_result(shell, value),
+        Err(err) => CommandResult::Error(CommandError::Parse(err)),
+    }
+}
+
+/// FNV-1a over the argument bytes, in the spirit of MOROS's built-in
+/// `hash` command — a small, immediately useful non-arithmetic tool.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    let mut hash = OFFSET_BASIS;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+fn cmd_hash(_shell: &mut Shell, rest: &str) -> CommandResult {
+    let hash = fnv1a_hash(rest.as_bytes());
+    let mut buf = [0u8; 20];
+    match uint_to_str_radix(hash, 16, &mut buf, true) {
+        Ok(output) => print_command_output(output),
+        Err(error) => return CommandResult::Error(CommandError::Parse(error)),
     }
+    print_command_output("\n");
+    CommandResult::Success
+}
+
+fn cmd_help(_shell: &mut Shell, _rest: &str) -> CommandResult {
+    for command in COMMANDS {
+        print_command_output(command.help);
+        print_command_output("\n");
+    }
+    CommandResult::Success
+}
+
+fn cmd_exit(_shell: &mut Shell, _rest: &str) -> CommandResult {
+    print_command_output("Exiting shell...\n");
+    CommandResult::Exit
 }
 
 fn print_string(s: &str, color_code: vga_buffer::ColorCode) {
     for byte in s.bytes() {
         vga_buffer::write_byte(byte, color_code);
     }
+    serial::write_str(s);
 }
 
 fn print_os_version(os_version: &str) {
@@ -118,42 +377,25 @@ impl Shell {
         Shell {
             buffer: [0; INPUT_BUFFER_LEN],
             len: 0,
-            extended_prefix: false,
             history: InputHistory::new(),
             saved_line: FixedString::new(),
             saved_line_active: false,
         }
     }
 
-    fn run(&mut self) -> ! {
+    fn run(&mut self, sources: &mut [&mut dyn CharSource]) -> ! {
         print_prompt();
         loop {
-            if let Some(scancode) = keyboard::pop_scancode() {
-                self.handle_scancode(scancode);
-            } else {
-                x86_64::instructions::hlt();
+            let mut received = false;
+            for source in sources.iter_mut() {
+                if let Some(byte) = source.next_byte() {
+                    self.handle_input_byte(byte);
+                    received = true;
+                }
             }
-        }
-    }
-
-    fn handle_scancode(&mut self, scancode: u8) {
-        if self.extended_prefix {
-            self.extended_prefix = false;
-            match scancode {
-                0x48 => self.handle_history_navigation(HistoryKey::Up),
-                0x50 => self.handle_history_navigation(HistoryKey::Down),
-                _ => {}
+            if !received {
+                x86_64::instructions::hlt();
             }
-            return;
-        }
-
-        if scancode == 0xE0 {
-            self.extended_prefix = true;
-            return;
-        }
-
-        if let Some(byte) = vga_buffer::scancode_to_ascii(scancode) {
-            self.handle_input_byte(byte);
         }
     }
 
@@ -168,6 +410,9 @@ impl Shell {
             0x08 => {
                 self.handle_backspace();
             }
+            HISTORY_UP => self.handle_history_navigation(HistoryKey::Up),
+            HISTORY_DOWN => self.handle_history_navigation(HistoryKey::Down),
+            TAB => self.handle_tab_completion(),
             _ => {
                 if self.len < self.buffer.len() {
                     self.reset_history_tracking();
@@ -182,6 +427,7 @@ impl Shell {
     fn echo_byte(&self, byte: u8) {
         let color_code = get_color_code(Color::White, Color::Black);
         vga_buffer::write_byte(byte, color_code);
+        serial::write_byte_blocking(byte);
     }
 
     fn clear_buffer(&mut self) {
@@ -227,9 +473,56 @@ impl Shell {
         }
     }
 
+    /// Complete the command name currently being typed against [`COMMANDS`].
+    /// A unique prefix match fills in the rest of the name; multiple matches
+    /// are listed below the prompt and the line is redrawn unchanged.
+    fn handle_tab_completion(&mut self) {
+        let owned_prefix = Self::own_line(self.current_line());
+        let prefix = owned_prefix.as_str();
+
+        if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+            return;
+        }
+
+        let mut matches = COMMANDS.iter().filter(|cmd| cmd.name.starts_with(prefix));
+        let count = matches.clone().count();
+
+        if count == 0 {
+            return;
+        }
+
+        if count > 1 {
+            print_command_output("\n");
+            for command in matches {
+                print_command_output(command.name);
+                print_command_output(" ");
+            }
+            print_command_output("\n");
+            print_prompt();
+            self.redraw_buffer();
+            return;
+        }
+
+        let completion = matches.next().expect("count == 1 implies a match");
+        for &byte in completion.name[prefix.len()..].as_bytes() {
+            if self.len < self.buffer.len() {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+                self.echo_byte(byte);
+            }
+        }
+    }
+
+    fn redraw_buffer(&self) {
+        for &byte in &self.buffer[..self.len] {
+            self.echo_byte(byte);
+        }
+    }
+
     fn erase_last_char(&self) {
         let color_code = get_color_code(Color::White, Color::Black);
         vga_buffer::backspace(color_code);
+        serial::write_str("\x08 \x08");
     }
 
     fn save_current_line(&mut self) {
@@ -317,66 +610,10 @@ impl Shell {
         self.history.push(line);
         self.reset_history_tracking();
 
-        match command_parser(line) {
-            Ok(command) => match self.execute_command(command) {
-                CommandResult::Success => {}
-                CommandResult::Exit => self.shutdown(),
-                CommandResult::Error(err) => self.report_error(err),
-            },
-            Err(err) => self.report_error(err),
-        }
-    }
-
-    fn execute_command<'a>(&self, command: CommandToExecute<'a>) -> CommandResult {
-        match command {
-            CommandToExecute::Greet { name } => {
-                let mut msg = FixedString::<64>::new();
-                let _ = msg.push_str("Hello, ");
-                let _ = msg.push_str(name);
-                let _ = msg.push_str("!\n");
-                print_command_output(msg.as_str());
-                CommandResult::Success
-            }
-            CommandToExecute::Sum { a, b } => {
-                let mut tmp_buf = [0u8; 32];
-                match int_to_str_buf(a + b, &mut tmp_buf) {
-                    Ok(output) => print_command_output(output),
-                    Err(error) => self.print_error(error.as_str()),
-                }
-                print_command_output("\n");
-                CommandResult::Success
-            }
-            CommandToExecute::Diff { a, b } => {
-                let mut tmp_buf = [0u8; 32];
-                match int_to_str_buf(a - b, &mut tmp_buf) {
-                    Ok(output) => print_command_output(output),
-                    Err(error) => self.print_error(error.as_str()),
-                }
-                print_command_output("\n");
-                CommandResult::Success
-            }
-            CommandToExecute::Min { a, b } => {
-                let mut tmp_buf = [0u8; 32];
-                match int_to_str_buf(core::cmp::min(a, b), &mut tmp_buf) {
-                    Ok(output) => print_command_output(output),
-                    Err(error) => self.print_error(error.as_str()),
-                }
-                print_command_output("\n");
-                CommandResult::Success
-            }
-            CommandToExecute::Max { a, b } => {
-                let mut tmp_buf = [0u8; 32];
-                match int_to_str_buf(core::cmp::max(a, b), &mut tmp_buf) {
-                    Ok(output) => print_command_output(output),
-                    Err(error) => self.print_error(error.as_str()),
-                }
-                print_command_output("\n");
-                CommandResult::Success
-            }
-            CommandToExecute::Exit => {
-                print_command_output("Exiting shell...\n");
-                CommandResult::Exit
-            }
+        match dispatch_command(self, line) {
+            CommandResult::Success => {}
+            CommandResult::Exit => self.shutdown(),
+            CommandResult::Error(err) => self.report_error(err),
         }
     }
 
@@ -406,10 +643,13 @@ impl Shell {
     }
 }
 
-pub fn bootstrap(os_version: &str) -> ! {
+/// Run the shell off whichever `CharSource`s the caller wants polled —
+/// e.g. a keyboard for normal boots, a serial source for headless
+/// `QEMU -nographic` runs, or both at once.
+pub fn bootstrap(os_version: &str, sources: &mut [&mut dyn CharSource]) -> ! {
     print_hello();
     print_os_version(os_version);
     print_string("\n", get_color_code(Color::White, Color::Black));
     let mut shell = Shell::new();
-    shell.run()
+    shell.run(sources)
 }
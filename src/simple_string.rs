@@ -1,7 +1,11 @@
 //! Minimal fixed-capacity string builder for the OS project.
 //!
 //! Inspired by `heapless::String`, but tiny and purpose-built for
-//! assembling short messages without pulling in `alloc::String`.
+//! assembling short messages without pulling in `alloc::String`. Still the
+//! right choice for anything that must work before `heap::init` runs, or
+//! that has a genuinely fixed bound (e.g. the shell's line-editing buffer);
+//! growable state like [`crate::history::InputHistory`] uses the
+//! heap instead.
 
 /// Errors that can occur when pushing into a [`FixedString`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
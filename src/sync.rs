@@ -0,0 +1,146 @@
+//! Spinlock-based synchronization primitives.
+//!
+//! Replaces the hand-rolled `UnsafeCell` + `unsafe impl Sync` + "SAFETY: no
+//! preemption yet" pattern used by the early globals (`keyboard::QUEUE`,
+//! `vga_buffer::WRITER`, ...) — now that the scheduler can preempt a task
+//! mid-access and the APIC path can deliver interrupts the PIC path
+//! couldn't, that assumption no longer holds.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::interrupts;
+
+/// Disables interrupts for as long as it's alive, restoring whatever the
+/// enabled/disabled state was *before* construction on drop — so a guard
+/// created while interrupts were already disabled (e.g. from inside an
+/// interrupt handler) doesn't re-enable them out from under the caller.
+pub struct InterruptGuard {
+    was_enabled: bool,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        let was_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        Self { was_enabled }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.was_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Test-and-set spinlock. Locking disables interrupts for the guard's
+/// lifetime (via [`InterruptGuard`]), so the holder can't be preempted
+/// mid-critical-section on the same CPU — the same protection the old
+/// `without_interrupts`-wrapped globals relied on, but paired with an
+/// actual lock instead of a bare assertion that nothing else is running.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let interrupts = InterruptGuard::new();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinLockGuard {
+            lock: self,
+            _interrupts: interrupts,
+        }
+    }
+
+    /// Convenience for the common "lock, run a closure, unlock" shape the
+    /// old `.with()` globals used.
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.lock())
+    }
+}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    _interrupts: InterruptGuard,
+}
+
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// One-time lazy-initialization cell, for statics like the IDT/GDT that
+/// must be built exactly once (typically on first use) and only read after
+/// that. `static TABLE: Once<Thing> = Once::new();` then
+/// `TABLE.get_or_init(|| build_thing())`.
+pub struct Once<T> {
+    initialized: AtomicBool,
+    value: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T> Sync for Once<T> {}
+
+impl<T> Once<T> {
+    pub const fn new() -> Self {
+        Self {
+            initialized: AtomicBool::new(false),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Run `f` and store its result the first time this is called; every
+    /// call (including the first) returns a reference to the stored value.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        let _interrupts = InterruptGuard::new();
+        if !self.initialized.load(Ordering::Acquire) {
+            unsafe { *self.value.get() = Some(f()) };
+            self.initialized.store(true, Ordering::Release);
+        }
+        unsafe { (*self.value.get()).as_ref().unwrap() }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.initialized.load(Ordering::Acquire) {
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+}
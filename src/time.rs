@@ -1,43 +1,277 @@
-//! Timekeeping scaffolding using the legacy PIT (8253/8254).
+//! Timekeeping using the legacy PIT (8253/8254), calibrated against the TSC.
 //!
-//! Plan:
-//! - Program PIT channel 0 to a desired frequency (e.g. 100 Hz, mode 3).
-//! - On each timer IRQ, call `time::tick()` to increment a global counter.
-//! - Provide `uptime_ticks()` and conversions to ms/sec via known HZ.
+//! - PIT channel 0 is programmed to a desired frequency (e.g. 100 Hz, mode 3)
+//!   and drives the coarse `TICKS` counter plus the timer wheel below.
+//! - PIT channel 2 is used once, at boot, to calibrate `rdtsc` against a
+//!   known-length interval so `now_ns`/`monotonic_ns` can offer sub-tick
+//!   resolution without floating point.
 //!
 //! Topics to read:
 //! - PIT ports (0x40..0x43), command word, divisor calculation
 //! - PIC routing of IRQ0 (we already remap PIC)
 //! - Atomic counters in `no_std`
+//! - PIT channel 2 / the 0x61 "NMI status" port gate and speaker bits
 
 #![allow(dead_code)]
 
+use core::arch::asm;
+use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
 
 static HZ: AtomicU32 = AtomicU32::new(18); // BIOS default ~18.2 Hz until programmed
 static TICKS: AtomicU64 = AtomicU64::new(0);
 
-/// Program PIT channel 0 for a given frequency (in Hz).
-///
-/// TODO (you implement):
-/// - Compute divisor: 1_193_182 / hz (clamp 1..=65535).
-/// - Write command to port 0x43 (e.g., 0x36 for ch0, lobyte/hibyte, mode 3).
-/// - Write low byte then high byte of divisor to port 0x40.
+/// Q32.32 fixed-point nanoseconds per TSC tick, i.e. `ns = (tsc * ratio) >> 32`.
+/// Zero means calibration failed or hasn't run yet; callers fall back to
+/// millisecond-granularity PIT time in that case.
+static TSC_NS_PER_TICK_Q32: AtomicU64 = AtomicU64::new(0);
+
+/// TSC value sampled the last time `tick()` ran, used to add sub-tick
+/// resolution on top of the coarse `TICKS` counter in `monotonic_ns`.
+static LAST_TICK_TSC: AtomicU64 = AtomicU64::new(0);
+
+const PIT_BASE_HZ: u32 = 1_193_182;
+
+/// Program PIT channel 0 for a given frequency (in Hz), then calibrate the
+/// TSC against PIT channel 2 for `now_ns`/`monotonic_ns`.
 pub fn init_pit(hz: u32) {
-    let _ = hz;
     unsafe {
         use crate::interrupts::outb;
-        let clamped = core::cmp::max(1, core::cmp::min(65_535, (1_193_182u32 / hz) as u32));
+        let clamped = core::cmp::max(1, core::cmp::min(65_535, PIT_BASE_HZ / hz));
         outb(0x43, 0x36);
         outb(0x40, (clamped & 0xFF) as u8);
         outb(0x40, (clamped >> 8) as u8);
     }
     HZ.store(hz, Ordering::Relaxed);
+
+    if let Some(ratio) = calibrate_tsc() {
+        TSC_NS_PER_TICK_Q32.store(ratio, Ordering::Relaxed);
+        LAST_TICK_TSC.store(rdtsc(), Ordering::Relaxed);
+    }
+}
+
+#[inline]
+fn rdtsc() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    unsafe {
+        asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    }
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Gate PIT channel 2 for a fixed interval (1/100 s worth of PIT clocks)
+/// and measure how many TSC ticks elapse, repeating a few times so a wildly
+/// varying (non-invariant) TSC can be detected and rejected.
+///
+/// Assumes a single CPU: on multi-core systems `rdtsc` is not guaranteed to
+/// stay in sync across cores, so this calibration (and `now_ns`/
+/// `monotonic_ns`) would need a per-core ratio and synchronized epoch.
+fn calibrate_tsc() -> Option<u64> {
+    const SAMPLE_HZ: u32 = 100;
+    const SAMPLE_NS: u64 = 1_000_000_000 / SAMPLE_HZ as u64;
+    const MAX_VARIANCE_PERMILLE: u64 = 50; // reject if samples disagree by >5%
+
+    let pit_ticks = core::cmp::max(1, core::cmp::min(0xFFFF, PIT_BASE_HZ / SAMPLE_HZ));
+
+    let mut samples = [0u64; 3];
+    for sample in samples.iter_mut() {
+        *sample = measure_tsc_delta_over_gate(pit_ticks as u16);
+    }
+
+    let mean = (samples[0] + samples[1] + samples[2]) / 3;
+    let max = *samples.iter().max().unwrap();
+    let min = *samples.iter().min().unwrap();
+    if mean == 0 {
+        return None;
+    }
+    if ((max - min) * 1000) / mean > MAX_VARIANCE_PERMILLE {
+        return None; // TSC rate looks unstable; stick to pure PIT ticks
+    }
+
+    Some((((SAMPLE_NS as u128) << 32) / mean as u128) as u64)
 }
 
-/// Increment system tick counter; call from timer IRQ handler.
+/// One gated measurement: arm channel 2 one-shot (mode 0) for `pit_ticks`
+/// PIT clocks, then busy-wait on the OUT2 status bit in port 0x61.
+fn measure_tsc_delta_over_gate(pit_ticks: u16) -> u64 {
+    unsafe {
+        use crate::interrupts::{inb, outb};
+
+        let ctrl = inb(0x61) & !0b11; // gate2 low, speaker off while we program it
+        outb(0x61, ctrl);
+
+        outb(0x43, 0b1011_0000); // channel 2, lobyte/hibyte, mode 0, binary
+        outb(0x42, (pit_ticks & 0xFF) as u8);
+        outb(0x42, (pit_ticks >> 8) as u8);
+
+        let start = rdtsc();
+        outb(0x61, ctrl | 0b01); // raise gate2: counting starts now
+        while inb(0x61) & 0b0010_0000 == 0 {
+            // OUT2 goes high once the count reaches zero (mode 0 terminal count)
+        }
+        let end = rdtsc();
+        outb(0x61, ctrl); // lower gate2 again
+
+        end.wrapping_sub(start)
+    }
+}
+
+/// Current time since boot in nanoseconds, derived from `rdtsc` scaled by
+/// the calibrated ratio. Falls back to millisecond PIT resolution if
+/// calibration failed.
+pub fn now_ns() -> u64 {
+    let ratio = TSC_NS_PER_TICK_Q32.load(Ordering::Relaxed);
+    if ratio == 0 {
+        return uptime_ms().saturating_mul(1_000_000);
+    }
+    scale_tsc_to_ns(rdtsc(), ratio)
+}
+
+/// Monotonic uptime in nanoseconds: the coarse tick counter plus the TSC
+/// delta since the last tick, for sub-millisecond resolution between ticks.
+pub fn monotonic_ns() -> u64 {
+    let ratio = TSC_NS_PER_TICK_Q32.load(Ordering::Relaxed);
+    if ratio == 0 {
+        return uptime_ms().saturating_mul(1_000_000);
+    }
+
+    let tick_period_ns = 1_000_000_000u64 / core::cmp::max(1, frequency_hz() as u64);
+    let coarse_ns = uptime_ticks().saturating_mul(tick_period_ns);
+
+    let since_tick = rdtsc().wrapping_sub(LAST_TICK_TSC.load(Ordering::Relaxed));
+    coarse_ns + scale_tsc_to_ns(since_tick, ratio)
+}
+
+fn scale_tsc_to_ns(tsc_ticks: u64, ratio_q32: u64) -> u64 {
+    ((tsc_ticks as u128 * ratio_q32 as u128) >> 32) as u64
+}
+
+/// Increment system tick counter and fire any due timers; call from the
+/// timer IRQ handler.
+///
+/// Invariant: this runs in interrupt context, so every registered callback
+/// must be short and non-blocking (no `sleep_ms`, no waiting on locks the
+/// foreground code might be holding).
 pub fn tick() {
-    TICKS.fetch_add(1, Ordering::Relaxed);
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    if TSC_NS_PER_TICK_Q32.load(Ordering::Relaxed) != 0 {
+        LAST_TICK_TSC.store(rdtsc(), Ordering::Relaxed);
+    }
+    TIMER_TABLE.with(|table| table.fire_due(now));
+}
+
+/// Busy-wait until `ms` milliseconds of uptime have passed.
+pub fn sleep_ms(ms: u64) {
+    let target = uptime_ticks().saturating_add(ms_to_ticks(ms).max(1));
+    while uptime_ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+fn ms_to_ticks(ms: u64) -> u64 {
+    (ms * frequency_hz() as u64) / 1_000
+}
+
+const MAX_TIMERS: usize = 32;
+
+/// Handle returned by `after_ms`/`every_ms`, passed to `cancel`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TimerHandle(usize);
+
+#[derive(Clone, Copy)]
+struct TimerSlot {
+    deadline_ticks: u64,
+    period_ticks: u64, // 0 means one-shot
+    callback: Option<fn()>,
+}
+
+impl TimerSlot {
+    const fn empty() -> Self {
+        Self { deadline_ticks: 0, period_ticks: 0, callback: None }
+    }
+}
+
+struct TimerTable {
+    slots: [TimerSlot; MAX_TIMERS],
+}
+
+impl TimerTable {
+    const fn new() -> Self {
+        Self { slots: [TimerSlot::empty(); MAX_TIMERS] }
+    }
+
+    fn schedule(&mut self, deadline_ticks: u64, period_ticks: u64, callback: fn()) -> TimerHandle {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.callback.is_none() {
+                *slot = TimerSlot { deadline_ticks, period_ticks, callback: Some(callback) };
+                return TimerHandle(index);
+            }
+        }
+        TimerHandle(MAX_TIMERS) // table full: handle refers to no slot, cancel is a no-op
+    }
+
+    fn cancel(&mut self, handle: TimerHandle) {
+        if let Some(slot) = self.slots.get_mut(handle.0) {
+            slot.callback = None;
+        }
+    }
+
+    /// Scan for and run every slot whose deadline has passed, rescheduling
+    /// periodic ones and clearing expired one-shots.
+    fn fire_due(&mut self, now: u64) {
+        for slot in self.slots.iter_mut() {
+            let Some(callback) = slot.callback else { continue };
+            if slot.deadline_ticks > now {
+                continue;
+            }
+            if slot.period_ticks > 0 {
+                slot.deadline_ticks += slot.period_ticks;
+            } else {
+                slot.callback = None;
+            }
+            callback();
+        }
+    }
+}
+
+struct SharedTimerTable(UnsafeCell<TimerTable>);
+
+unsafe impl Sync for SharedTimerTable {}
+
+impl SharedTimerTable {
+    const fn new() -> Self {
+        Self(UnsafeCell::new(TimerTable::new()))
+    }
+
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut TimerTable) -> R,
+    {
+        without_interrupts(|| unsafe { f(&mut *self.0.get()) })
+    }
+}
+
+static TIMER_TABLE: SharedTimerTable = SharedTimerTable::new();
+
+/// Run `callback` once, roughly `ms` milliseconds from now.
+pub fn after_ms(ms: u64, callback: fn()) -> TimerHandle {
+    let deadline = uptime_ticks().saturating_add(ms_to_ticks(ms).max(1));
+    TIMER_TABLE.with(|table| table.schedule(deadline, 0, callback))
+}
+
+/// Run `callback` every `ms` milliseconds, starting `ms` milliseconds from now.
+pub fn every_ms(ms: u64, callback: fn()) -> TimerHandle {
+    let period = ms_to_ticks(ms).max(1);
+    let deadline = uptime_ticks().saturating_add(period);
+    TIMER_TABLE.with(|table| table.schedule(deadline, period, callback))
+}
+
+/// Cancel a previously scheduled timer. A no-op if it already fired
+/// (one-shot) or was already cancelled.
+pub fn cancel(handle: TimerHandle) {
+    TIMER_TABLE.with(|table| table.cancel(handle));
 }
 
 /// Current ticks since boot.
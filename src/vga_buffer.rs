@@ -1,4 +1,6 @@
-use core::{arch::asm, cell::UnsafeCell};
+use core::arch::asm;
+
+use crate::sync::SpinLock;
 
 #[repr(u8)]
 pub enum Color {
@@ -199,25 +201,7 @@ impl Writer {
     }
 }
 
-struct GlobalWriter(UnsafeCell<Writer>);
-
-impl GlobalWriter {
-    const fn new(writer: Writer) -> Self {
-        Self(UnsafeCell::new(writer))
-    }
-
-    fn with<F, R>(&self, f: F) -> R
-    where
-        F: FnOnce(&mut Writer) -> R,
-    {
-        // SAFETY: OS kernel runs on a single core without preemption yet.
-        unsafe { f(&mut *self.0.get()) }
-    }
-}
-
-unsafe impl Sync for GlobalWriter {}
-
-static WRITER: GlobalWriter = GlobalWriter::new(Writer {
+static WRITER: SpinLock<Writer> = SpinLock::new(Writer {
     color_code: ColorCode::new(Color::White, Color::Black),
     row: 0,
     column: 0,
@@ -230,3 +214,34 @@ pub fn write_byte(byte: u8, color_code: ColorCode) {
         writer.write_byte(byte);
     });
 }
+
+impl core::fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Used by the `print!`/`println!` macros below; not meant to be called
+/// directly.
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+    WRITER.with(|writer| {
+        writer.color_code = get_color_code(Color::White, Color::Black);
+        let _ = writer.write_fmt(args);
+    });
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_print(core::format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", core::format_args!($($arg)*)));
+}
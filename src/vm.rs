@@ -0,0 +1,256 @@
+//! A tiny `no_std` register-machine bytecode interpreter for the shell's
+//! `run` command, modeled loosely after holey-bytes: a flat register file,
+//! a program counter, and a byte-addressable program slice decoded one
+//! instruction at a time. No allocation, no panics — every way an ill-formed
+//! program can misbehave is reported as a [`Trap`] instead.
+//!
+//! Instruction encoding: one opcode byte, then fixed operand layouts keyed
+//! by the opcode:
+//! - `R`   — one register index
+//! - `RR`  — two register indices
+//! - `RRR` — three register indices (dest, lhs, rhs)
+//! - `RD`  — one register index + an 8-byte little-endian immediate
+//! - `D`   — just an 8-byte little-endian immediate
+
+#![allow(dead_code)]
+
+const NUM_REGISTERS: usize = 256;
+const RETURN_STACK_DEPTH: usize = 64;
+
+mod opcode {
+    pub const HALT: u8 = 0x00;
+    pub const LOAD_IMM: u8 = 0x01; // RD:  reg <- imm
+    pub const MOV: u8 = 0x02;      // RR:  dst <- src
+    pub const ADD: u8 = 0x03;      // RRR: dst <- lhs + rhs
+    pub const SUB: u8 = 0x04;      // RRR: dst <- lhs - rhs
+    pub const MUL: u8 = 0x05;      // RRR: dst <- lhs * rhs
+    pub const DIV: u8 = 0x06;      // RRR: dst <- lhs / rhs (signed)
+    pub const CMP: u8 = 0x07;      // RRR: dst <- -1/0/1 for lhs </==/> rhs
+    pub const JMP: u8 = 0x08;      // D:   pc <- pc + offset
+    pub const JNZ: u8 = 0x09;      // RD:  if reg != 0, pc <- pc + offset
+    pub const CALL: u8 = 0x0A;     // D:   push return pc, pc <- pc + offset
+    pub const RET: u8 = 0x0B;      // (none): pop return pc
+}
+
+/// Why the VM stopped running. Every variant carries the `pc` it stopped at
+/// so the shell can print something actionable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    Halted { pc: usize },
+    InvalidOpcode { pc: usize, op: u8 },
+    DivideByZero { pc: usize },
+    PcOutOfBounds { pc: usize },
+    ReturnStackOverflow { pc: usize },
+    ReturnStackUnderflow { pc: usize },
+}
+
+impl Trap {
+    /// Render into a caller-provided buffer; avoids pulling in `format!`.
+    pub fn describe<'a>(&self, buf: &'a mut [u8]) -> &'a str {
+        use crate::parser::uint_to_str_radix;
+
+        let (label, pc) = match *self {
+            Trap::Halted { pc } => ("halted", pc),
+            Trap::InvalidOpcode { pc, .. } => ("invalid opcode", pc),
+            Trap::DivideByZero { pc } => ("divide by zero", pc),
+            Trap::PcOutOfBounds { pc } => ("pc out of bounds", pc),
+            Trap::ReturnStackOverflow { pc } => ("return stack overflow", pc),
+            Trap::ReturnStackUnderflow { pc } => ("return stack underflow", pc),
+        };
+
+        let mut cursor = 0;
+        for &byte in label.as_bytes() {
+            if cursor >= buf.len() {
+                break;
+            }
+            buf[cursor] = byte;
+            cursor += 1;
+        }
+        for &byte in b" at pc=" {
+            if cursor >= buf.len() {
+                break;
+            }
+            buf[cursor] = byte;
+            cursor += 1;
+        }
+
+        let mut addr_buf = [0u8; 20];
+        if let Ok(addr_str) = uint_to_str_radix(pc as u64, 16, &mut addr_buf, true) {
+            for &byte in addr_str.as_bytes() {
+                if cursor >= buf.len() {
+                    break;
+                }
+                buf[cursor] = byte;
+                cursor += 1;
+            }
+        }
+
+        core::str::from_utf8(&buf[..cursor]).unwrap_or("")
+    }
+}
+
+/// A register-machine interpreter over a borrowed program image.
+pub struct Vm<'a> {
+    pub registers: [u64; NUM_REGISTERS],
+    pc: usize,
+    program: &'a [u8],
+    return_stack: [usize; RETURN_STACK_DEPTH],
+    return_sp: usize,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a [u8]) -> Self {
+        Self {
+            registers: [0; NUM_REGISTERS],
+            pc: 0,
+            program,
+            return_stack: [0; RETURN_STACK_DEPTH],
+            return_sp: 0,
+        }
+    }
+
+    pub fn register_i64(&self, index: u8) -> i64 {
+        self.registers[index as usize] as i64
+    }
+
+    pub fn register_f64(&self, index: u8) -> f64 {
+        f64::from_bits(self.registers[index as usize])
+    }
+
+    /// Run starting at `start_pc` until a `Trap` stops it (every program
+    /// traps eventually — even a clean finish is `Trap::Halted`).
+    pub fn run_from(&mut self, start_pc: usize) -> Trap {
+        self.pc = start_pc;
+        loop {
+            if let Err(trap) = self.step() {
+                return trap;
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Trap> {
+        let op = self.fetch_u8()?;
+        match op {
+            opcode::HALT => return Err(Trap::Halted { pc: self.pc - 1 }),
+            opcode::LOAD_IMM => {
+                let (dst, imm) = self.fetch_rd()?;
+                self.registers[dst as usize] = imm as u64;
+            }
+            opcode::MOV => {
+                let (dst, src) = self.fetch_rr()?;
+                self.registers[dst as usize] = self.registers[src as usize];
+            }
+            opcode::ADD => {
+                let (dst, lhs, rhs) = self.fetch_rrr()?;
+                self.registers[dst as usize] =
+                    self.register_i64(lhs).wrapping_add(self.register_i64(rhs)) as u64;
+            }
+            opcode::SUB => {
+                let (dst, lhs, rhs) = self.fetch_rrr()?;
+                self.registers[dst as usize] =
+                    self.register_i64(lhs).wrapping_sub(self.register_i64(rhs)) as u64;
+            }
+            opcode::MUL => {
+                let (dst, lhs, rhs) = self.fetch_rrr()?;
+                self.registers[dst as usize] =
+                    self.register_i64(lhs).wrapping_mul(self.register_i64(rhs)) as u64;
+            }
+            opcode::DIV => {
+                let (dst, lhs, rhs) = self.fetch_rrr()?;
+                let divisor = self.register_i64(rhs);
+                if divisor == 0 {
+                    return Err(Trap::DivideByZero { pc: self.pc });
+                }
+                self.registers[dst as usize] = self.register_i64(lhs).wrapping_div(divisor) as u64;
+            }
+            opcode::CMP => {
+                let (dst, lhs, rhs) = self.fetch_rrr()?;
+                let ordering = self.register_i64(lhs).cmp(&self.register_i64(rhs));
+                self.registers[dst as usize] = ordering as i64 as u64;
+            }
+            opcode::JMP => {
+                let offset = self.fetch_i64()?;
+                self.jump_relative(offset)?;
+            }
+            opcode::JNZ => {
+                let (reg, offset) = self.fetch_rd()?;
+                if self.registers[reg as usize] != 0 {
+                    self.jump_relative(offset)?;
+                }
+            }
+            opcode::CALL => {
+                let offset = self.fetch_i64()?;
+                if self.return_sp >= RETURN_STACK_DEPTH {
+                    return Err(Trap::ReturnStackOverflow { pc: self.pc });
+                }
+                self.return_stack[self.return_sp] = self.pc;
+                self.return_sp += 1;
+                self.jump_relative(offset)?;
+            }
+            opcode::RET => {
+                if self.return_sp == 0 {
+                    return Err(Trap::ReturnStackUnderflow { pc: self.pc });
+                }
+                self.return_sp -= 1;
+                self.pc = self.return_stack[self.return_sp];
+            }
+            other => return Err(Trap::InvalidOpcode { pc: self.pc - 1, op: other }),
+        }
+        Ok(())
+    }
+
+    fn jump_relative(&mut self, offset: i64) -> Result<(), Trap> {
+        let target = (self.pc as i64)
+            .checked_add(offset)
+            .ok_or(Trap::PcOutOfBounds { pc: self.pc })?;
+        if target < 0 || target as usize > self.program.len() {
+            return Err(Trap::PcOutOfBounds { pc: self.pc });
+        }
+        self.pc = target as usize;
+        Ok(())
+    }
+
+    fn fetch_u8(&mut self) -> Result<u8, Trap> {
+        let byte = *self.program.get(self.pc).ok_or(Trap::PcOutOfBounds { pc: self.pc })?;
+        self.pc += 1;
+        Ok(byte)
+    }
+
+    fn fetch_i64(&mut self) -> Result<i64, Trap> {
+        if self.pc + 8 > self.program.len() {
+            return Err(Trap::PcOutOfBounds { pc: self.pc });
+        }
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.program[self.pc..self.pc + 8]);
+        self.pc += 8;
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn fetch_rr(&mut self) -> Result<(u8, u8), Trap> {
+        Ok((self.fetch_u8()?, self.fetch_u8()?))
+    }
+
+    fn fetch_rrr(&mut self) -> Result<(u8, u8, u8), Trap> {
+        Ok((self.fetch_u8()?, self.fetch_u8()?, self.fetch_u8()?))
+    }
+
+    fn fetch_rd(&mut self) -> Result<(u8, i64), Trap> {
+        let reg = self.fetch_u8()?;
+        let imm = self.fetch_i64()?;
+        Ok((reg, imm))
+    }
+}
+
+/// A tiny built-in program (`r0 <- (2 + 3) * 4`, then halt) so the shell's
+/// `run` command has something to execute before there's a real assembler
+/// or loader. `addr` in `run <addr>` is a byte offset into this buffer.
+pub fn demo_program() -> &'static [u8] {
+    &[
+        opcode::LOAD_IMM, 1, 2, 0, 0, 0, 0, 0, 0, 0,
+        opcode::LOAD_IMM, 2, 3, 0, 0, 0, 0, 0, 0, 0,
+        opcode::ADD, 0, 1, 2,
+        opcode::LOAD_IMM, 3, 4, 0, 0, 0, 0, 0, 0, 0,
+        opcode::MUL, 0, 0, 3,
+        opcode::HALT,
+    ]
+}